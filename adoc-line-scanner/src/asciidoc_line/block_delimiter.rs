@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub struct BlockDelimiter<'a> {
     pub delimiter: &'a str,
     pub level: usize,
@@ -6,7 +7,7 @@ pub struct BlockDelimiter<'a> {
 impl<'a> TryFrom<&'a str> for BlockDelimiter<'a> {
     type Error = ();
 
-    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+    fn try_from(_value: &'a str) -> Result<Self, Self::Error> {
         todo!()
     }
-}
\ No newline at end of file
+}
@@ -4,6 +4,7 @@ use crate::error::LineScannerError;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AsciidocLine<'a> {
+    #[allow(clippy::upper_case_acronyms)]
     EOF,
     /// An line containing nothing or only whitespace
     Empty,
@@ -28,6 +29,7 @@ impl<'a> From<Option<&'a str>> for AsciidocLine<'a> {
 
 impl<'a> AsciidocLine<'a> {
     /// If EOF or empty, return a mismatch. Otherwise, apply `f`
+    #[allow(dead_code)]
     fn flat_map_line<F, B>(self, f: F) -> Result<B, LineScannerError>
     where
         F: Fn(&'a str) -> Result<B, LineScannerError>,
@@ -38,6 +40,7 @@ impl<'a> AsciidocLine<'a> {
         }
     }
     /// If EOF or empty, return a mismatch. Otherwise, apply `f` and wrap in `Ok`
+    #[allow(dead_code)]
     fn map_line<F, B>(self, f: F) -> Result<B, LineScannerError>
     where
         F: Fn(&'a str) -> B,
@@ -47,4 +50,4 @@ impl<'a> AsciidocLine<'a> {
             AsciidocLine::Line(s) => Ok(f(s)),
         }
     }
-}
\ No newline at end of file
+}
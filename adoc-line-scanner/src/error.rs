@@ -1,3 +1,3 @@
 pub enum LineScannerError {
     Mismatch,
-}
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
-mod line_scanner;
 mod asciidoc_line;
 mod error;
+mod line_scanner;
 
+pub use error::LineScannerError;
 pub use line_scanner::LineScanner;
-pub use error::LineScannerError;
\ No newline at end of file
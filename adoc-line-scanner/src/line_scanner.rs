@@ -12,15 +12,17 @@ pub struct LineScanner<'a> {
 /// Token iterators
 impl<'a> LineScanner<'a> {
     /// Returns one physical line
-    fn next(&mut self) -> AsciidocLine {
+    fn next(&mut self) -> AsciidocLine<'a> {
         self.inner.next().into()
     }
-    fn peek(&mut self) -> AsciidocLine {
-        self.inner.peek().map(|s| *s).into()
+    fn peek(&mut self) -> AsciidocLine<'a> {
+        self.inner.peek().copied().into()
     }
-    fn peek_nth(&mut self, n: usize) -> AsciidocLine {
-        self.inner.peek_nth(n).map(|s| *s).into()
+    #[allow(dead_code)]
+    fn peek_nth(&mut self, n: usize) -> AsciidocLine<'a> {
+        self.inner.peek_nth(n).copied().into()
     }
+    #[allow(dead_code)]
     fn advance_if_ok<T>(&mut self, x: Result<T, LineScannerError>) -> Result<T, LineScannerError> {
         if x.is_ok() {
             self.next();
@@ -30,6 +32,7 @@ impl<'a> LineScanner<'a> {
     fn advance(&mut self) {
         self.next();
     }
+    #[allow(dead_code)]
     fn advance_by(&mut self, n: usize) {
         for _ in 0..n {
             self.advance();
@@ -51,20 +54,10 @@ impl<'a> LineScanner<'a> {
     //     self.peek().map_line(str::to_owned)
     // }
     pub fn peek_empty(&mut self) -> bool {
-        let next = self.peek();
-        if matches!(next, AsciidocLine::Empty) {
-            true
-        } else {
-            false
-        }
+        matches!(self.peek(), AsciidocLine::Empty)
     }
     pub fn peek_eof(&mut self) -> bool {
-        let next = self.peek();
-        if matches!(next, AsciidocLine::EOF) {
-            true
-        } else {
-            false
-        }
+        matches!(self.peek(), AsciidocLine::EOF)
     }
 }
 
@@ -95,10 +88,10 @@ impl<'a> LineScanner<'a> {
     pub fn delimited_block(&mut self) -> Vec<String> {
         todo!()
     }
-    /// A block where every line is prefixed by `prefix`. 
-    /// 
+    /// A block where every line is prefixed by `prefix`.
+    ///
     /// For instance, an indented block.
-    pub fn prefixed_block(&mut self, prefix: &str) -> Vec<String> {
+    pub fn prefixed_block(&mut self, _prefix: &str) -> Vec<String> {
         todo!()
     }
 }
@@ -0,0 +1,166 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    inline::{Inline, InlineKind},
+    macros,
+    subs::{replace_all, Node, Stashed},
+};
+
+/// An inline anchor, `[[id]]`, `[[id,reftext]]` or `anchor:id[reftext]`, or a bibliography
+/// anchor, `[[[id]]]` or `[[[id,label]]]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub id: String,
+    /// For bibliography anchors, the label or the ID in brackets
+    pub reftext: Option<String>,
+    pub bibliography: bool,
+}
+
+/// Substitute inline anchors in text nodes. IDs are taken as they are written, and may
+/// contain replaced text such as `--`.
+pub fn substitute_anchors(mut nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_BIBLIOGRAPHY: Regex =
+            Regex::new(r"(\\)?\[\[\[([\w:\p{Co}][\w:.\-\p{Co}]*)(?:, *(.+?))?\]\]\]").unwrap();
+        static ref RE_ANCHOR: Regex =
+            Regex::new(r"(\\)?\[\[([\w:\p{Co}][\w:.\-\p{Co}]*)(?:, *(.+?))?\]\]").unwrap();
+        static ref RE_MACRO: Regex =
+            Regex::new(r"(\\)?anchor:([\w:\p{Co}][\w:.\-\p{Co}]*)\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_BIBLIOGRAPHY, |caps| {
+            let label = caps.get(3).map(|l| l.as_str()).unwrap_or(&caps[2]);
+            let reftext = Some(format!("[{}]", s.text(label.trim())));
+            anchor(s, caps, reftext, true)
+        })
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_ANCHOR, |caps| {
+            let reftext = caps.get(3).map(|r| s.text(r.as_str().trim()));
+            anchor(s, caps, reftext, false)
+        })
+    });
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MACRO, |caps| {
+            let reftext = Some(macros::unescape(&s.text(&caps[3]))).filter(|r| !r.is_empty());
+            anchor(s, caps, reftext, false)
+        })
+    })
+}
+
+/// An anchor, unless its ID is made of other markup, such as quoted text
+fn anchor(
+    s: &Stashed,
+    caps: &Captures,
+    reftext: Option<String>,
+    bibliography: bool,
+) -> Option<Vec<Node>> {
+    lazy_static! {
+        static ref RE_ID: Regex = Regex::new(r"^[\w:][\w:.\-]*$").unwrap();
+    }
+    if caps.get(1).is_some() {
+        return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+    }
+    let id = s.source(&caps[2]);
+    if !RE_ID.is_match(&id) {
+        return None;
+    }
+    let inline = Inline::new(InlineKind::Anchor(Anchor {
+        id,
+        reftext,
+        bibliography,
+    }));
+    Some(vec![Node::Inline(inline, Vec::new())])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        document::Document, inline::QuoteType, quotes::substitute_quotes,
+        replacements::substitute_replacements, subs::apply_with,
+    };
+
+    fn anchor(id: &str, reftext: Option<&str>, bibliography: bool) -> Inline {
+        Inline::new(InlineKind::Anchor(Anchor {
+            id: id.to_owned(),
+            reftext: reftext.map(str::to_owned),
+            bibliography,
+        }))
+    }
+
+    #[test]
+    fn anchor_forms() {
+        assert_eq!(
+            apply_with(
+                "[[a]]x [[b, Bee]] anchor:c[See] [[[d]]] [[[e,E2]]] \\[[f]]",
+                substitute_anchors
+            ),
+            vec![
+                anchor("a", None, false),
+                Inline::unquoted("x "),
+                anchor("b", Some("Bee"), false),
+                Inline::unquoted(" "),
+                anchor("c", Some("See"), false),
+                Inline::unquoted(" "),
+                anchor("d", Some("[d]"), true),
+                Inline::unquoted(" "),
+                anchor("e", Some("[E2]"), true),
+                Inline::unquoted(" [[f]]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replacements_in_anchors() {
+        assert_eq!(
+            apply_with("[[a--b, it's]] [[*c*]]", |nodes| {
+                substitute_anchors(substitute_replacements(substitute_quotes(nodes)))
+            }),
+            vec![
+                anchor("a--b", Some("it\u{2019}s"), false),
+                Inline::unquoted(" [["),
+                Inline::new(InlineKind::Quoted {
+                    ty: QuoteType::Strong,
+                    content: vec![Inline::unquoted("c")]
+                }),
+                Inline::unquoted("]]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn anchors_in_catalog() {
+        let doc = Document::parse(
+            "Some text.\n\nA [[step,the step]]step and [#term]#a term#.\n\n\
+             * item anchor:item[]\n\nSee <<step>>, <<term>> and <<item>>.",
+        )
+        .unwrap();
+        let step = &doc.catalog.refs["step"];
+        assert_eq!(step.reftext.as_deref(), Some("the step"));
+        assert_eq!(step.span.start.line, 3);
+        assert_eq!(doc.catalog.refs["term"].reftext.as_deref(), Some("a term"));
+        assert_eq!(doc.catalog.refs["item"].span.start.line, 5);
+        assert!(doc.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let doc = Document::parse(
+            "[#a]\n== One\n\n[[a]]\n== Two\n\n[#b]\nText [[c]] and [[c]].\n\n\
+             [bibliography]\n== References\n\n* [[[b]]] Pragmatic.",
+        )
+        .unwrap();
+        let messages: Vec<_> = doc.diagnostics.iter().map(|d| &d.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "id assigned to section already in use: a",
+                "id assigned to anchor already in use: c",
+                "id assigned to block already in use: b",
+            ]
+        );
+        assert_eq!(doc.catalog.refs["a"].title.as_deref(), Some("One"));
+    }
+}
@@ -1,24 +1,38 @@
-use std::iter::Peekable;
-
 use crate::ast::reader::{
-    self, {Parser, ParserError},
+    self, AdmonitionLine, AdmonitionType, BlockAnchor, BlockMacro, BlockTitle, BreakLine, Context,
+    Delimiter, DocumentAttrEntry, LineComment, ListItemLine, Parser, ParserError, SectionTitle,
 };
 
-use super::{element::ElementAttrs, inline::Inline};
+use super::{
+    breaks,
+    document::Doctype,
+    element::ElementAttrs,
+    inline::Inline,
+    line_scanner::LineScanner,
+    math::{Math, MathNotation},
+    media::{AudioBlock, ImageBlock, VideoBlock},
+    section::{self, SectionStyle},
+    source::SourceBlock,
+    span::{Position, Span},
+    state::ParserState,
+    subs::{Substitution, Substitutions},
+    table::TableBlock,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Block {
     pub attrs: Option<ElementAttrs>,
     pub anchor: Option<String>,
     pub title: Option<Vec<Inline>>,
     pub style: BlockStyle,
     pub context: BlockContext,
-    // pub span: Span,
+    pub span: Span,
     // TODO delimiters
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum BlockStyle {
+    #[default]
     Normal,
     Literal,
     Verse,
@@ -37,40 +51,65 @@ pub enum BlockStyle {
     Source,
 }
 
-impl Default for BlockStyle {
-    fn default() -> Self {
-        Self::Normal
+impl BlockStyle {
+    /// Style from the first positional attribute. Admonition styles are upper case.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "normal" => Some(Self::Normal),
+            "literal" => Some(Self::Literal),
+            "verse" => Some(Self::Verse),
+            "quote" => Some(Self::Quote),
+            "listing" => Some(Self::Listing),
+            "TIP" => Some(Self::Tip),
+            "NOTE" => Some(Self::Note),
+            "IMPORTANT" => Some(Self::Important),
+            "WARNING" => Some(Self::Warning),
+            "CAUTION" => Some(Self::Caution),
+            "abstract" => Some(Self::Abstract),
+            "partintro" => Some(Self::PartIntro),
+            "comment" => Some(Self::Comment),
+            "example" => Some(Self::Example),
+            "sidebar" => Some(Self::Sidebar),
+            "source" => Some(Self::Source),
+            _ => None,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BlockContext {
-    Admonition(SimpleBlock),
-    Audio,
+    Admonition(AdmonitionBlock),
+    Audio(AudioBlock),
     CalloutList(CompoundBlock),
     DescriptionList(CompoundBlock),
-    Example(SimpleBlock),
-    FloatingTitle(String),
-    Image,
+    Example(CompoundBlock),
+    FloatingTitle(FloatingTitleBlock),
+    Image(ImageBlock),
     ListItem(CompoundBlock),
     Listing(VerbatimBlock),
     Literal(VerbatimBlock),
     OrderedList(CompoundBlock),
     Open(BlockContent),
-    PageBreak,
+    PageBreak(PageBreakBlock),
     Paragraph(SimpleBlock),
+    /// A level-0 section of a book
+    Part(SectionBlock),
     Passthrough(String),
-    Quote(SimpleBlock),
-    Section(CompoundBlock),
-    Sidebar(SimpleBlock),
-    Table(CompoundBlock),
+    Quote(QuoteBlock),
+    Section(SectionBlock),
+    Sidebar(CompoundBlock),
+    Source(SourceBlock),
+    /// A `[stem]`, `[asciimath]` or `[latexmath]` passthrough
+    Stem(Math),
+    Table(TableBlock),
     TableCell(CompoundBlock),
     ThematicBreak,
     UnorderedList(CompoundBlock),
-    Verse(SimpleBlock),
+    Verse(QuoteBlock),
+    Video(VideoBlock),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BlockContent {
     Compound(CompoundBlock),
     Simple(SimpleBlock),
@@ -79,43 +118,1059 @@ pub enum BlockContent {
     Empty,
 }
 
-#[derive(Debug, Clone)]
-pub struct CompoundBlock(Vec<Block>);
-#[derive(Debug, Clone)]
-pub struct SimpleBlock(Vec<Inline>);
-#[derive(Debug, Clone)]
-pub struct VerbatimBlock(Vec<Inline>);
-#[derive(Debug, Clone)]
-pub struct RawBlock(String);
-
-// impl<'a> Element<'a> for Block {
-//     /// Assumes already at first line
-//     fn parse(s: &mut Peekable<PhysicalLineIter<'a>>) -> Result<Self, ParserError> {
-//         // There are multiple possibilities for the first line
-//         let first = s.next().expect("expects non-empty first line");
-//         // let attrs;
-//         // let anchor;
-//         // let title;
-//         // let style;
-//         // let context;
-//         // If line looks like an attribute, advance
-//         if let Ok(a) = reader::element_attr::ElementAttrs::parse(&first.content) {
-//             // attrs = Some(a.into());
-//             // We look ahead to see if first line is really an attribute
-//             let second = s.peek();
-//             if second.is_none() {
-//                 // If second line is empty
-//             }
-//         }
-//         // TODO shorthands?
-
-//         todo!()
-//         // Ok(Self {
-//         //     attrs,
-//         //     anchor,
-//         //     title,
-//         //     style,
-//         //     context,
-//         // })
-//     }
-// }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompoundBlock(pub Vec<Block>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleBlock(pub Vec<Inline>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerbatimBlock(pub Vec<Inline>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawBlock(pub String);
+
+/// An admonition paragraph (simple content) or block (compound content)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmonitionBlock {
+    pub ty: AdmonitionType,
+    /// Caption from the `caption` attribute, or the document's caption for the type
+    pub caption: String,
+    pub content: BlockContent,
+}
+
+impl AdmonitionBlock {
+    fn new(
+        ty: AdmonitionType,
+        content: BlockContent,
+        meta: &BlockMetadata,
+        state: &ParserState,
+    ) -> Self {
+        let caption = meta
+            .attrs
+            .as_ref()
+            .and_then(|a| a.get("caption"))
+            .or_else(|| state.attrs.get(&format!("{}-caption", ty.name())))
+            .unwrap_or(ty.label())
+            .to_owned();
+        Self {
+            ty,
+            caption,
+            content,
+        }
+    }
+}
+
+/// A quote or verse, as a paragraph (simple content) or a block (compound content for
+/// quotes, simple content for verses)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteBlock {
+    pub attribution: Option<String>,
+    pub citetitle: Option<String>,
+    pub content: BlockContent,
+}
+
+impl QuoteBlock {
+    /// Attribution and citation title from the `attribution` and `citetitle` attributes, or
+    /// from the second and third positional attributes
+    pub fn new(content: BlockContent, attrs: Option<&ElementAttrs>) -> Self {
+        let attr = |name, index| {
+            attrs
+                .and_then(|a| a.get(name).or_else(|| a.positional(index)))
+                .filter(|v| !v.is_empty())
+                .map(str::to_owned)
+        };
+        Self {
+            attribution: attr("attribution", 1),
+            citetitle: attr("citetitle", 2),
+            content,
+        }
+    }
+    /// Attribution and citation title from a line like `-- Author, Source`
+    fn attributed(content: BlockContent, line: &str) -> Self {
+        let line = line.trim_start_matches("--").trim();
+        let (attribution, citetitle) = match line.split_once(", ") {
+            Some((a, c)) => (a.trim(), Some(c.trim().to_owned())),
+            None => (line, None),
+        };
+        Self {
+            attribution: Some(attribution.to_owned()).filter(|a| !a.is_empty()),
+            citetitle,
+            content,
+        }
+    }
+}
+
+/// A discrete heading, a section title with the `discrete` or `float` style. It is not part
+/// of the section tree. The title is the block title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FloatingTitleBlock {
+    pub level: usize,
+}
+
+/// A page break, `<<<`. Only paged backends, such as PDF, use it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageBreakBlock {
+    /// Break even at the top of a page, from the `always` option
+    pub always: bool,
+    /// Layout of the next page, such as `landscape`, from the `page-layout` attribute
+    pub page_layout: Option<String>,
+}
+
+impl PageBreakBlock {
+    fn new(attrs: Option<&ElementAttrs>) -> Self {
+        Self {
+            always: attrs.map(|a| a.has_option("always")).unwrap_or(false),
+            page_layout: attrs.and_then(|a| a.get("page-layout")).map(str::to_owned),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionBlock {
+    pub level: usize,
+    /// Style of a special section, if allowed at this level and in this doctype
+    pub style: Option<SectionStyle>,
+    /// Section number, such as `1.2` or `A`
+    pub number: Option<String>,
+    /// Caption preceding the title, such as `Appendix A: `
+    pub caption: Option<String>,
+    pub blocks: Vec<Block>,
+}
+
+impl SimpleBlock {
+    pub fn new(lines: &[String], subs: &Substitutions, state: &ParserState) -> Self {
+        Self(subs.apply(&lines.join("\n"), state))
+    }
+}
+
+impl VerbatimBlock {
+    pub fn new(lines: &[String], subs: &Substitutions, state: &ParserState) -> Self {
+        Self(subs.apply(&lines.join("\n"), state))
+    }
+}
+
+/// Where section titles may start a section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionScope {
+    /// Sections are not allowed, e.g. in a delimited block
+    None,
+    /// At the top level of a document
+    Document,
+    /// Inside a section of the given level
+    Section(usize),
+}
+
+/// Attributes, anchor and title lines preceding a block
+#[derive(Debug, Clone, Default)]
+pub struct BlockMetadata {
+    pub attrs: Option<ElementAttrs>,
+    pub anchor: Option<String>,
+    pub title: Option<Vec<Inline>>,
+}
+
+impl BlockMetadata {
+    /// Scan metadata lines. Empty lines and comments between them are skipped. The title
+    /// has normal substitutions.
+    pub fn parse(scanner: &mut LineScanner, state: &ParserState) -> Self {
+        let mut meta = Self::default();
+        loop {
+            scanner.empty_lines();
+            if let Ok(anchor) = scanner.parse::<BlockAnchor>() {
+                if let Some(reftext) = anchor.reftext {
+                    meta.attrs
+                        .get_or_insert_with(Default::default)
+                        .insert("reftext", &reftext);
+                }
+                meta.anchor = Some(anchor.id);
+            } else if let Ok(attrs) = scanner.element_attrs() {
+                match meta.attrs.as_mut() {
+                    Some(a) => a.merge(attrs),
+                    None => meta.attrs = Some(attrs),
+                }
+            } else if let Ok(title) = scanner.parse::<BlockTitle>() {
+                meta.title = Some(Substitutions::normal().apply(&title.0, state));
+            } else if scanner.parse::<LineComment>().is_ok() {
+                continue;
+            } else {
+                break;
+            }
+        }
+        if let Some(id) = meta.attrs.as_ref().and_then(|a| a.id()) {
+            meta.anchor = Some(id.to_owned());
+        }
+        meta
+    }
+    pub fn style_name(&self) -> Option<&str> {
+        self.attrs.as_ref().and_then(|a| a.style())
+    }
+    pub fn style(&self) -> BlockStyle {
+        self.style_name()
+            .and_then(BlockStyle::from_name)
+            .unwrap_or_default()
+    }
+    /// Simple content with normal substitutions, unless the `subs` attribute says otherwise.
+    /// With the `hardbreaks` option of the block or the document, each line is broken,
+    /// except in verses, which keep their lines anyway.
+    pub fn simple(&self, lines: &[String], state: &ParserState) -> SimpleBlock {
+        let subs = Substitutions::for_block(Substitutions::normal(), self.attrs.as_ref());
+        let block = SimpleBlock::new(lines, &subs, state);
+        let hardbreaks = self.attrs.as_ref().map(|a| a.has_option("hardbreaks"));
+        let hardbreaks = hardbreaks.unwrap_or(false) || state.attrs.is_set("hardbreaks-option");
+        match hardbreaks
+            && self.style_name() != Some("verse")
+            && subs.contains(Substitution::PostReplacements)
+        {
+            true => SimpleBlock(breaks::hard_breaks(block.0)),
+            false => block,
+        }
+    }
+    /// Verbatim content with verbatim substitutions, unless the `subs` attribute says otherwise
+    pub fn verbatim(&self, lines: &[String], state: &ParserState) -> VerbatimBlock {
+        let subs = Substitutions::for_block(Substitutions::verbatim(), self.attrs.as_ref());
+        VerbatimBlock::new(lines, &subs, state)
+    }
+    /// Raw content without substitutions, unless the `subs` attribute says otherwise
+    pub fn raw(&self, lines: &[String], state: &ParserState) -> String {
+        let subs = Substitutions::for_block(Substitutions::none(), self.attrs.as_ref());
+        subs.apply_raw(&lines.join("\n"), state)
+    }
+    /// Raw content, which is a formula with a STEM style
+    pub fn passthrough(&self, lines: &[String], state: &ParserState) -> BlockContext {
+        let source = self.raw(lines, state);
+        let notation = self
+            .style_name()
+            .and_then(|style| MathNotation::from_name(style, &state.attrs));
+        match notation {
+            Some(notation) => BlockContext::Stem(Math {
+                notation,
+                source,
+                display: true,
+            }),
+            None => BlockContext::Passthrough(source),
+        }
+    }
+    pub fn into_block(self, context: BlockContext, span: Span) -> Block {
+        let style = self.style();
+        Block {
+            attrs: self.attrs,
+            anchor: self.anchor,
+            title: self.title,
+            style,
+            context,
+            span,
+        }
+    }
+}
+
+impl Block {
+    /// A block without metadata
+    pub fn new(context: BlockContext, span: Span) -> Self {
+        BlockMetadata::default().into_block(context, span)
+    }
+}
+
+/// Traversal
+impl Block {
+    /// Blocks nested in this one
+    pub fn children_mut(&mut self) -> Vec<&mut Block> {
+        let content = match &mut self.context {
+            BlockContext::Admonition(AdmonitionBlock { content, .. })
+            | BlockContext::Open(content)
+            | BlockContext::Quote(QuoteBlock { content, .. })
+            | BlockContext::Verse(QuoteBlock { content, .. }) => content,
+            BlockContext::CalloutList(CompoundBlock(blocks))
+            | BlockContext::DescriptionList(CompoundBlock(blocks))
+            | BlockContext::Example(CompoundBlock(blocks))
+            | BlockContext::ListItem(CompoundBlock(blocks))
+            | BlockContext::OrderedList(CompoundBlock(blocks))
+            | BlockContext::Sidebar(CompoundBlock(blocks))
+            | BlockContext::TableCell(CompoundBlock(blocks))
+            | BlockContext::UnorderedList(CompoundBlock(blocks))
+            | BlockContext::Part(SectionBlock { blocks, .. })
+            | BlockContext::Section(SectionBlock { blocks, .. }) => {
+                return blocks.iter_mut().collect()
+            }
+            BlockContext::Table(table) => {
+                return table
+                    .header
+                    .iter_mut()
+                    .chain(table.body.iter_mut())
+                    .chain(table.footer.iter_mut())
+                    .flat_map(|row| row.0.iter_mut().map(|cell| &mut cell.block))
+                    .collect()
+            }
+            _ => return Vec::new(),
+        };
+        match content {
+            BlockContent::Compound(CompoundBlock(blocks)) => blocks.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Inline content of this block, without that of nested blocks. The title comes first.
+    pub fn inlines_mut(&mut self) -> Vec<&mut Vec<Inline>> {
+        let mut inlines: Vec<&mut Vec<Inline>> = self.title.iter_mut().collect();
+        let content = match &mut self.context {
+            BlockContext::Admonition(AdmonitionBlock { content, .. })
+            | BlockContext::Open(content)
+            | BlockContext::Quote(QuoteBlock { content, .. })
+            | BlockContext::Verse(QuoteBlock { content, .. }) => match content {
+                BlockContent::Simple(SimpleBlock(content))
+                | BlockContent::Verbatim(VerbatimBlock(content)) => Some(content),
+                _ => None,
+            },
+            BlockContext::Paragraph(SimpleBlock(content))
+            | BlockContext::Listing(VerbatimBlock(content))
+            | BlockContext::Literal(VerbatimBlock(content))
+            | BlockContext::Source(SourceBlock {
+                content: VerbatimBlock(content),
+                ..
+            }) => Some(content),
+            _ => None,
+        };
+        inlines.extend(content);
+        inlines
+    }
+
+    /// Visit this block and the blocks nested in it, depth first
+    pub fn walk_mut<F: FnMut(&mut Block)>(&mut self, f: &mut F) {
+        f(self);
+        for child in self.children_mut() {
+            child.walk_mut(f);
+        }
+    }
+
+    /// Visit the inlines of `blocks` and of the blocks nested in them, in document order,
+    /// with the span of their block
+    pub fn walk_inlines_mut<F: FnMut(&mut Inline, Span)>(blocks: &mut [Block], f: &mut F) {
+        for block in blocks {
+            block.walk_mut(&mut |b| {
+                let span = b.span;
+                for inlines in b.inlines_mut() {
+                    Inline::walk_mut(inlines, &mut |inline| f(inline, span));
+                }
+            });
+        }
+    }
+}
+
+/// Block-level parsing
+impl Block {
+    /// Parse blocks until EOF, or until a section title that closes `scope`.
+    pub fn parse_blocks(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        scope: SectionScope,
+    ) -> Result<Vec<Block>, ParserError> {
+        let mut blocks = Vec::new();
+        loop {
+            scanner.empty_lines();
+            if scanner.peek_eof() {
+                break;
+            }
+            if scanner.peek_parse::<DocumentAttrEntry>().is_ok() {
+                state.attrs.entry(scanner)?;
+                continue;
+            }
+            if let SectionScope::Section(level) = scope {
+                if matches!(Self::peek_section_level(scanner), Some(l) if l <= level) {
+                    break;
+                }
+            }
+            let meta = BlockMetadata::parse(scanner, state);
+            if scanner.peek_eof() {
+                break;
+            }
+            blocks.extend(Self::parse_one(scanner, state, scope, meta)?);
+        }
+        Ok(blocks)
+    }
+
+    /// Parse blocks in the content of a delimited block or a table cell, which starts at
+    /// `origin` in the enclosing file
+    pub fn parse_lines(
+        lines: &[String],
+        origin: Position,
+        state: &mut ParserState,
+        scope: SectionScope,
+    ) -> Result<Vec<Block>, ParserError> {
+        let text = lines.join("\n");
+        let mut scanner = LineScanner::with_origin(&text, origin);
+        Self::parse_blocks(&mut scanner, state, scope)
+    }
+
+    /// Level of the section title following any metadata lines, without consuming them.
+    /// Discrete headings are not section titles.
+    fn peek_section_level(scanner: &mut LineScanner) -> Option<usize> {
+        let mut n = 0;
+        loop {
+            if scanner.peek_nth_eof(n) {
+                return None;
+            }
+            if let Ok(title) = scanner.peek_nth_parse::<SectionTitle>(n) {
+                return Some(title.level);
+            }
+            if let Ok(attrs) = scanner.peek_nth_parse::<reader::ElementAttrs>(n) {
+                let attrs = ElementAttrs::from(attrs).with_shorthand();
+                if matches!(attrs.style(), Some("discrete" | "float")) {
+                    return None;
+                }
+                n += 1;
+            } else if scanner.peek_nth_empty(n)
+                || scanner.peek_nth_parse::<BlockAnchor>(n).is_ok()
+                || scanner.peek_nth_parse::<BlockTitle>(n).is_ok()
+                || scanner.peek_nth_parse::<LineComment>(n).is_ok()
+            {
+                n += 1;
+            } else {
+                return None;
+            }
+        }
+    }
+
+    /// Parse the block following its metadata, or the blocks a block processor replaces it
+    /// with
+    pub fn parse_one(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        scope: SectionScope,
+        meta: BlockMetadata,
+    ) -> Result<Vec<Block>, ParserError> {
+        if let Some(blocks) = Self::process(scanner, state, &meta)? {
+            return Ok(blocks);
+        }
+        Ok(Self::parse_single(scanner, state, scope, meta)?
+            .into_iter()
+            .collect())
+    }
+
+    /// A paragraph or delimited block with the style of a registered block processor. Its
+    /// lines are replaced by the blocks the processor returns, which get the span of the
+    /// block. The first one also gets its anchor and title, unless it has its own.
+    fn process(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        meta: &BlockMetadata,
+    ) -> Result<Option<Vec<Block>>, ParserError> {
+        let processor = match meta.style_name() {
+            Some(style) => match state.extensions.find_block_processor(style) {
+                Some(processor) => processor,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let start = scanner.position();
+        let lines = match scanner.peek_delimiter() {
+            // Comments and tables are not processed
+            Ok(delimiter) => match delimiter.default_context_content().0 {
+                None | Some(Context::Table) => return Ok(None),
+                Some(_) => scanner.delimited_block()?,
+            },
+            Err(_)
+                if scanner.peek_parse::<SectionTitle>().is_ok()
+                    || scanner.peek_parse::<BlockMacro>().is_ok()
+                    || scanner.peek_parse::<ListItemLine>().is_ok() =>
+            {
+                return Ok(None)
+            }
+            Err(_) => scanner.paragraph_lines(),
+        };
+        let attrs = meta.attrs.clone().unwrap_or_default();
+        let mut blocks = processor(&lines, &attrs, state);
+        let span = Span {
+            start,
+            end: scanner.end_position(),
+        };
+        for block in &mut blocks {
+            block.span = span;
+        }
+        if let Some(first) = blocks.first_mut() {
+            if first.anchor.is_none() {
+                first.anchor = meta.anchor.clone();
+            }
+            if first.title.is_none() {
+                first.title = meta.title.clone();
+            }
+        }
+        if let Some(anchor) = &meta.anchor {
+            state.register_id(anchor);
+        }
+        Ok(Some(blocks))
+    }
+
+    fn parse_single(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        scope: SectionScope,
+        mut meta: BlockMetadata,
+    ) -> Result<Option<Block>, ParserError> {
+        let start = scanner.position();
+        // Duplicate IDs are reported by the catalog
+        if let Some(anchor) = &meta.anchor {
+            state.register_id(anchor);
+        }
+        // Discrete headings may appear anywhere
+        let discrete = matches!(meta.style_name(), Some("discrete" | "float"));
+        let section_title = match scope {
+            SectionScope::None if !discrete => Err(ParserError::Mismatch),
+            _ => scanner.parse::<SectionTitle>(),
+        };
+        let context = if let Ok(title) = section_title {
+            let inlines = Substitutions::normal().apply(&title.title, state);
+            if meta.anchor.is_none() && state.attrs.is_set("sectids") {
+                meta.anchor = Some(state.generate_id(&Inline::text(&inlines)));
+            }
+            meta.title = Some(inlines);
+            if discrete {
+                let context =
+                    BlockContext::FloatingTitle(FloatingTitleBlock { level: title.level });
+                let span = Span {
+                    start,
+                    end: scanner.end_position(),
+                };
+                return Ok(Some(meta.into_block(context, span)));
+            }
+            let doctype = state.attrs.doctype();
+            let style = meta
+                .style_name()
+                .and_then(SectionStyle::from_name)
+                .filter(|style| style.is_allowed(doctype, title.level));
+            let mut numbering = std::mem::take(&mut state.numbering);
+            let number = numbering.next(title.level, style, state);
+            state.numbering = numbering;
+            let caption = section::caption(style, number.as_deref(), state);
+            let mut blocks =
+                Self::parse_blocks(scanner, state, SectionScope::Section(title.level))?;
+            if style == Some(SectionStyle::Bibliography) {
+                section::bibliography(&mut blocks, state);
+            }
+            let section = SectionBlock {
+                level: title.level,
+                style,
+                number,
+                caption,
+                blocks,
+            };
+            match (doctype, title.level) {
+                (Doctype::Book, 0) => BlockContext::Part(section::part(section)),
+                _ => BlockContext::Section(section),
+            }
+        } else if let Ok(line) = scanner.parse::<BreakLine>() {
+            // Checked before lists and delimiters, since `* * *` and `---` look alike
+            match line {
+                BreakLine::Thematic => BlockContext::ThematicBreak,
+                BreakLine::Page => {
+                    BlockContext::PageBreak(PageBreakBlock::new(meta.attrs.as_ref()))
+                }
+            }
+        } else if scanner.peek_parse::<ListItemLine>().is_ok() {
+            return Self::list(scanner, state, meta, &[]).map(Some);
+        } else if scanner
+            .peek_non_empty_line()
+            .map(|l| l == ">" || l.starts_with("> "))
+            .unwrap_or(false)
+        {
+            let lines = scanner.prefixed_block(">");
+            let origin = Position::new(start.line, start.column + 2);
+            Self::markdown_quote(lines, origin, state)?
+        } else if let Some(context) = scanner
+            .peek_parse::<BlockMacro>()
+            .ok()
+            .and_then(|m| Self::block_macro(m, state, &mut meta))
+        {
+            scanner.non_empty_line()?;
+            context
+        } else if let Ok(delimiter) = scanner.peek_delimiter() {
+            let lines = scanner.delimited_block()?;
+            let origin = Position::new(start.line + 1, 1);
+            match Self::delimited(delimiter, lines, origin, state, &meta)? {
+                Some(context) => context,
+                None => return Ok(None),
+            }
+        } else {
+            let lines = scanner.paragraph_lines();
+            match Self::paragraph(lines, &meta, state) {
+                Some(context) => context,
+                None => return Ok(None),
+            }
+        };
+        let span = Span {
+            start,
+            end: scanner.end_position(),
+        };
+        Ok(Some(meta.into_block(context, span)))
+    }
+
+    fn delimited(
+        delimiter: Delimiter,
+        lines: Vec<String>,
+        origin: Position,
+        state: &mut ParserState,
+        meta: &BlockMetadata,
+    ) -> Result<Option<BlockContext>, ParserError> {
+        let context = match delimiter.default_context_content().0 {
+            // Comment
+            None => return Ok(None),
+            Some(Context::Open) => match meta.style_name() {
+                Some("comment") => return Ok(None),
+                Some("literal") => Context::Literal,
+                Some("listing") | Some("source") => Context::Listing,
+                Some("pass") => Context::Passthrough,
+                Some("example") => Context::Example,
+                Some("sidebar") => Context::Sidebar,
+                Some("quote") => Context::Quote,
+                Some("verse") => Context::Verse,
+                _ => Context::Open,
+            },
+            Some(Context::Quote) if meta.style_name() == Some("verse") => Context::Verse,
+            Some(Context::Literal) if matches!(meta.style_name(), Some("listing" | "source")) => {
+                Context::Listing
+            }
+            Some(Context::Listing) if meta.style_name() == Some("literal") => Context::Literal,
+            Some(context) => context,
+        };
+        let compound = |state: &mut ParserState| {
+            Self::parse_lines(&lines, origin, state, SectionScope::None).map(CompoundBlock)
+        };
+        let admonition = meta.style_name().and_then(AdmonitionType::from_label);
+        let context = match (context, admonition) {
+            (Context::Example | Context::Open, Some(ty)) => {
+                let content = BlockContent::Compound(compound(state)?);
+                return Ok(Some(BlockContext::Admonition(AdmonitionBlock::new(
+                    ty, content, meta, state,
+                ))));
+            }
+            (context, _) => context,
+        };
+        let context = match context {
+            Context::Open => BlockContext::Open(BlockContent::Compound(compound(state)?)),
+            Context::Example => BlockContext::Example(compound(state)?),
+            Context::Sidebar => BlockContext::Sidebar(compound(state)?),
+            Context::Quote => {
+                let content = BlockContent::Compound(compound(state)?);
+                BlockContext::Quote(QuoteBlock::new(content, meta.attrs.as_ref()))
+            }
+            // Verses keep line breaks and whitespace
+            Context::Verse => {
+                let content = BlockContent::Simple(meta.simple(&lines, state));
+                BlockContext::Verse(QuoteBlock::new(content, meta.attrs.as_ref()))
+            }
+            // Listings are source blocks with the source style, or by default when a source
+            // language is set
+            Context::Listing => match (delimiter, meta.style_name()) {
+                (Delimiter::Fenced(language), _) => {
+                    let attrs = meta.attrs.as_ref();
+                    BlockContext::Source(SourceBlock::new(&lines, language, attrs, state))
+                }
+                (_, Some("source")) => {
+                    let attrs = meta.attrs.as_ref();
+                    BlockContext::Source(SourceBlock::new(&lines, None, attrs, state))
+                }
+                (_, None) if state.attrs.is_set("source-language") => {
+                    let attrs = meta.attrs.as_ref();
+                    BlockContext::Source(SourceBlock::new(&lines, None, attrs, state))
+                }
+                _ => BlockContext::Listing(meta.verbatim(&lines, state)),
+            },
+            Context::Literal => BlockContext::Literal(meta.verbatim(&lines, state)),
+            Context::Passthrough => meta.passthrough(&lines, state),
+            Context::Table => {
+                let separator = match delimiter {
+                    Delimiter::Table(c, _) => c,
+                    _ => unreachable!(),
+                };
+                let attrs = meta.attrs.clone().unwrap_or_default();
+                BlockContext::Table(TableBlock::parse(&lines, origin, separator, &attrs, state)?)
+            }
+            _ => unreachable!("delimited block context {:?}", context),
+        };
+        Ok(Some(context))
+    }
+
+    fn paragraph(
+        mut lines: Vec<String>,
+        meta: &BlockMetadata,
+        state: &ParserState,
+    ) -> Option<BlockContext> {
+        let indented = lines
+            .first()
+            .map(|l| l.starts_with(char::is_whitespace))
+            .unwrap_or(false);
+        if let Some(ty) = meta.style_name().and_then(AdmonitionType::from_label) {
+            let content = BlockContent::Simple(meta.simple(&lines, state));
+            return Some(BlockContext::Admonition(AdmonitionBlock::new(
+                ty, content, meta, state,
+            )));
+        }
+        let context = match meta.style_name() {
+            Some("comment") => return None,
+            Some("literal") => BlockContext::Literal(meta.verbatim(&lines, state)),
+            Some("listing") => BlockContext::Listing(meta.verbatim(&lines, state)),
+            Some("source") => {
+                BlockContext::Source(SourceBlock::new(&lines, None, meta.attrs.as_ref(), state))
+            }
+            Some("pass" | "stem" | "asciimath" | "latexmath") => meta.passthrough(&lines, state),
+            Some("quote") => {
+                let content = BlockContent::Simple(meta.simple(&lines, state));
+                BlockContext::Quote(QuoteBlock::new(content, meta.attrs.as_ref()))
+            }
+            Some("verse") => {
+                let content = BlockContent::Simple(meta.simple(&lines, state));
+                BlockContext::Verse(QuoteBlock::new(content, meta.attrs.as_ref()))
+            }
+            None if indented => BlockContext::Literal(meta.verbatim(&dedent(&lines), state)),
+            _ => match lines.first().map(|l| AdmonitionLine::parse(l)) {
+                Some(Ok(first)) => {
+                    lines[0] = first.text;
+                    let content = BlockContent::Simple(meta.simple(&lines, state));
+                    BlockContext::Admonition(AdmonitionBlock::new(first.ty, content, meta, state))
+                }
+                _ => match Self::air_quote(&lines) {
+                    Some((quoted, attribution)) => {
+                        let content = BlockContent::Simple(meta.simple(&quoted, state));
+                        BlockContext::Quote(QuoteBlock::attributed(content, attribution))
+                    }
+                    None => BlockContext::Paragraph(meta.simple(&lines, state)),
+                },
+            },
+        };
+        Some(context)
+    }
+
+    /// Block macros with a built-in meaning. Named attributes of the block attribute line
+    /// apply to the macro too.
+    fn block_macro(
+        m: BlockMacro,
+        state: &ParserState,
+        meta: &mut BlockMetadata,
+    ) -> Option<BlockContext> {
+        let mut attrs = ElementAttrs::from(m.attrs);
+        if let Some(block_attrs) = &meta.attrs {
+            attrs.inherit(block_attrs);
+        }
+        let context = match m.name.as_str() {
+            "image" => BlockContext::Image(ImageBlock::new(&m.target, &attrs, state)),
+            "video" => BlockContext::Video(VideoBlock::new(&m.target, &attrs, state)),
+            "audio" => BlockContext::Audio(AudioBlock::new(&m.target, &attrs, state)),
+            name => state.extensions.find_block_macro(name)?(&m.target, &attrs),
+        };
+        if meta.title.is_none() {
+            meta.title = attrs
+                .get("title")
+                .map(|t| Substitutions::normal().apply(t, state));
+        }
+        Some(context)
+    }
+
+    /// A quoted paragraph, `"text"` followed by an attribution line `-- Author, Source`.
+    /// Returns the text without quotes and the attribution line.
+    fn air_quote(lines: &[String]) -> Option<(Vec<String>, &str)> {
+        let (attribution, quoted) = lines.split_last()?;
+        let (first, last) = (quoted.first()?, quoted.last()?);
+        if !attribution.starts_with("-- ")
+            || !first.starts_with('"')
+            || !last.trim_end().ends_with('"')
+            || (quoted.len() == 1 && first.trim_end().len() < 2)
+        {
+            return None;
+        }
+        let mut quoted = quoted.to_vec();
+        quoted[0].remove(0);
+        let last = quoted.last_mut().unwrap();
+        last.truncate(last.trim_end().len() - 1);
+        Some((quoted, attribution))
+    }
+
+    /// A Markdown-style quote, where each line starts with `>`. The prefix is stripped and the
+    /// rest parsed as blocks. A last line `-- Author, Source` is the attribution.
+    fn markdown_quote(
+        lines: Vec<String>,
+        origin: Position,
+        state: &mut ParserState,
+    ) -> Result<BlockContext, ParserError> {
+        let mut lines: Vec<String> = lines
+            .iter()
+            .map(|l| l.strip_prefix(' ').unwrap_or(l).to_owned())
+            .collect();
+        let attribution = match lines.last() {
+            Some(last) if last.starts_with("-- ") => lines.pop(),
+            _ => None,
+        };
+        let blocks = Self::parse_lines(&lines, origin, state, SectionScope::None)?;
+        let content = BlockContent::Compound(CompoundBlock(blocks));
+        Ok(BlockContext::Quote(match attribution {
+            Some(line) => QuoteBlock::attributed(content, &line),
+            None => QuoteBlock::new(content, None),
+        }))
+    }
+}
+
+/// Remove the indentation common to all non-empty lines
+pub fn dedent(lines: &[String]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        document::Document,
+        inline::{InlineKind, QuoteType},
+    };
+
+    fn admonitions(s: &str) -> Vec<AdmonitionBlock> {
+        Document::parse(s)
+            .unwrap()
+            .blocks
+            .into_iter()
+            .map(|b| match b.context {
+                BlockContext::Admonition(a) => a,
+                other => panic!("expected an admonition, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn admonition_paragraphs() {
+        let a = admonitions("NOTE: Read this\nand this.\n\n[TIP]\nA tip.");
+        assert_eq!(a[0].ty, AdmonitionType::Note);
+        assert_eq!(a[0].caption, "Note");
+        assert_eq!(
+            a[0].content,
+            BlockContent::Simple(SimpleBlock(vec![Inline::unquoted("Read this\nand this.")]))
+        );
+        assert_eq!(a[1].ty, AdmonitionType::Tip);
+        assert_eq!(a[1].caption, "Tip");
+    }
+
+    #[test]
+    fn admonition_blocks() {
+        let a =
+            admonitions("[WARNING]\n====\nFirst.\n\nSecond.\n====\n\n[CAUTION]\n--\n* item\n--");
+        assert_eq!(a[0].ty, AdmonitionType::Warning);
+        assert!(matches!(&a[0].content, BlockContent::Compound(c) if c.0.len() == 2));
+        assert_eq!(a[1].ty, AdmonitionType::Caution);
+        assert!(matches!(&a[1].content, BlockContent::Compound(c) if c.0.len() == 1));
+    }
+
+    #[test]
+    fn admonition_captions() {
+        let a = admonitions(
+            ":note-caption: Hinweis\n\nNOTE: Lies das.\n\n[IMPORTANT, caption=Achtung]\nWichtig.",
+        );
+        assert_eq!(a[0].caption, "Hinweis");
+        assert_eq!(a[1].caption, "Achtung");
+    }
+
+    fn quotes(s: &str) -> Vec<(&'static str, QuoteBlock)> {
+        Document::parse(s)
+            .unwrap()
+            .blocks
+            .into_iter()
+            .map(|b| match b.context {
+                BlockContext::Quote(q) => ("quote", q),
+                BlockContext::Verse(q) => ("verse", q),
+                other => panic!("expected a quote, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn simple(s: &str) -> BlockContent {
+        BlockContent::Simple(SimpleBlock(vec![Inline::unquoted(s)]))
+    }
+
+    #[test]
+    fn quote_blocks() {
+        let q = quotes(
+            "[quote, Albert Einstein, Speech to Students]\n____\nFirst.\n\nSecond.\n____\n\n\
+             [quote, attribution=Anonymous]\nA quoted paragraph.",
+        );
+        assert_eq!(q[0].0, "quote");
+        assert_eq!(q[0].1.attribution.as_deref(), Some("Albert Einstein"));
+        assert_eq!(q[0].1.citetitle.as_deref(), Some("Speech to Students"));
+        assert!(matches!(&q[0].1.content, BlockContent::Compound(c) if c.0.len() == 2));
+        assert_eq!(q[1].1.attribution.as_deref(), Some("Anonymous"));
+        assert_eq!(q[1].1.citetitle, None);
+        assert_eq!(q[1].1.content, simple("A quoted paragraph."));
+    }
+
+    #[test]
+    fn verse_blocks() {
+        let q = quotes(
+            "[verse, Carl Sandburg, Fog]\n____\nThe fog comes\n  on little cat feet.\n\nIt sits\n____\n\n\
+             [verse]\nA verse\n   paragraph",
+        );
+        assert_eq!(q[0].0, "verse");
+        assert_eq!(q[0].1.attribution.as_deref(), Some("Carl Sandburg"));
+        assert_eq!(
+            q[0].1.content,
+            simple("The fog comes\n  on little cat feet.\n\nIt sits")
+        );
+        assert_eq!(q[1].0, "verse");
+        assert_eq!(q[1].1.content, simple("A verse\n   paragraph"));
+    }
+
+    #[test]
+    fn markdown_and_air_quotes() {
+        let q = quotes(
+            "> A quote\n> over lines\n>\n> * with a list\n> -- Jane Doe, The Book\n\n\
+             \"I hold it that a little rebellion\nis a good thing.\"\n-- Thomas Jefferson, Papers",
+        );
+        assert_eq!(q[0].1.attribution.as_deref(), Some("Jane Doe"));
+        assert_eq!(q[0].1.citetitle.as_deref(), Some("The Book"));
+        match &q[0].1.content {
+            BlockContent::Compound(c) => {
+                assert_eq!(c.0.len(), 2);
+                assert!(matches!(c.0[1].context, BlockContext::UnorderedList(_)));
+            }
+            other => panic!("unexpected content {:?}", other),
+        }
+        assert_eq!(q[1].1.attribution.as_deref(), Some("Thomas Jefferson"));
+        assert_eq!(q[1].1.citetitle.as_deref(), Some("Papers"));
+        assert_eq!(
+            q[1].1.content,
+            simple("I hold it that a little rebellion\nis a good thing.")
+        );
+    }
+
+    #[test]
+    fn breaks() {
+        let doc = Document::parse(
+            "a\n\n'''\n\n  * * *\n\n[%always, page-layout=landscape]\n<<<\n\n---\n\n----\ncode\n----",
+        )
+        .unwrap();
+        let contexts: Vec<_> = doc.blocks.iter().map(|b| &b.context).collect();
+        assert!(matches!(contexts[0], BlockContext::Paragraph(_)));
+        assert_eq!(contexts[1], &BlockContext::ThematicBreak);
+        assert_eq!(contexts[2], &BlockContext::ThematicBreak);
+        assert_eq!(
+            contexts[3],
+            &BlockContext::PageBreak(PageBreakBlock {
+                always: true,
+                page_layout: Some("landscape".to_owned()),
+            })
+        );
+        assert_eq!(contexts[4], &BlockContext::ThematicBreak);
+        assert!(matches!(contexts[5], BlockContext::Listing(_)));
+    }
+
+    #[test]
+    fn discrete_headings() {
+        let doc = Document::parse(
+            "== Section\n\n[discrete]\n== Heading\n\ntext\n\n====\n[float]\n=== Inside\n====\n\n== Next",
+        )
+        .unwrap();
+        assert_eq!(doc.blocks.len(), 2);
+        let section = match &doc.blocks[0].context {
+            BlockContext::Section(section) => section,
+            other => panic!("expected a section, got {:?}", other),
+        };
+        assert_eq!(section.blocks.len(), 3);
+        let heading = &section.blocks[0];
+        assert_eq!(
+            heading.context,
+            BlockContext::FloatingTitle(FloatingTitleBlock { level: 1 })
+        );
+        assert_eq!(heading.anchor.as_deref(), Some("_heading"));
+        assert_eq!(heading.title, Some(vec![Inline::unquoted("Heading")]));
+        match &section.blocks[2].context {
+            BlockContext::Example(example) => assert_eq!(
+                example.0[0].context,
+                BlockContext::FloatingTitle(FloatingTitleBlock { level: 2 })
+            ),
+            other => panic!("expected an example, got {:?}", other),
+        }
+        assert_eq!(doc.blocks[1].anchor.as_deref(), Some("_next"));
+    }
+
+    #[test]
+    fn block_substitutions() {
+        let doc = Document::parse(
+            ":v: 1.0\n\nVersion *{v}*\n\n[subs=none]\nVersion *{v}*\n\n\
+             [subs=+attributes]\n....\nVersion *{v}*\n....\n\n[subs=attributes]\n++++\n<b>{v}</b>\n++++",
+        )
+        .unwrap();
+        let contexts: Vec<_> = doc.blocks.into_iter().map(|b| b.context).collect();
+        assert_eq!(
+            contexts[0],
+            BlockContext::Paragraph(SimpleBlock(vec![
+                Inline::unquoted("Version "),
+                Inline::new(InlineKind::Quoted {
+                    ty: QuoteType::Strong,
+                    content: vec![Inline::unquoted("1.0")]
+                })
+            ]))
+        );
+        assert_eq!(
+            contexts[1],
+            BlockContext::Paragraph(SimpleBlock(vec![Inline::unquoted("Version *{v}*")]))
+        );
+        assert_eq!(
+            contexts[2],
+            BlockContext::Literal(VerbatimBlock(vec![Inline::unquoted("Version *1.0*")]))
+        );
+        assert_eq!(
+            contexts[3],
+            BlockContext::Passthrough("<b>1.0</b>".to_owned())
+        );
+    }
+
+    #[test]
+    fn title_substitutions() {
+        let quoted = |ty, s: &str| {
+            Inline::new(InlineKind::Quoted {
+                ty,
+                content: vec![Inline::unquoted(s)],
+            })
+        };
+        let doc = Document::parse(
+            ":product: Cargo\n\n== Using *{product}*\n\n.A _small_ example\n====\ntext\n====",
+        )
+        .unwrap();
+        let section = &doc.blocks[0];
+        assert_eq!(section.anchor.as_deref(), Some("_using_cargo"));
+        assert_eq!(
+            section.title,
+            Some(vec![
+                Inline::unquoted("Using "),
+                quoted(QuoteType::Strong, "Cargo")
+            ])
+        );
+        let example = match &section.context {
+            BlockContext::Section(section) => &section.blocks[0],
+            other => panic!("expected a section, got {:?}", other),
+        };
+        assert_eq!(
+            example.title,
+            Some(vec![
+                Inline::unquoted("A "),
+                quoted(QuoteType::Emphasis, "small"),
+                Inline::unquoted(" example")
+            ])
+        );
+    }
+
+    #[test]
+    fn whitespace_lines() {
+        let doc =
+            Document::parse("----\n  \ncode\n----\n\n[verse]\n____\nline\n \nline\n____").unwrap();
+        assert_eq!(
+            doc.blocks[0].context,
+            BlockContext::Listing(VerbatimBlock(vec![Inline::unquoted("  \ncode")]))
+        );
+        match &doc.blocks[1].context {
+            BlockContext::Verse(verse) => assert_eq!(
+                verse.content,
+                BlockContent::Simple(SimpleBlock(vec![Inline::unquoted("line\n \nline")]))
+            ),
+            other => panic!("expected a verse, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    inline::{Inline, InlineKind},
+    subs::{replace_all, Node},
+};
+
+/// Substitute hard line breaks, ` +` at the end of a line, in text nodes. The newline is
+/// kept after the break.
+pub fn substitute_line_breaks(nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?m) \+$").unwrap();
+    }
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE, |_| {
+            Some(vec![Node::Inline(line_break(), Vec::new())])
+        })
+    })
+}
+
+/// Break every line of `inlines`, for the `hardbreaks` option. Lines which already end
+/// with a break are kept. Passthroughs and the raw text of unknown macros are not broken.
+pub fn hard_breaks(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut broken: Vec<Inline> = Vec::new();
+    for mut inline in inlines {
+        let text = match inline.kind {
+            InlineKind::Unquoted(text) => text,
+            InlineKind::Passthrough(_) | InlineKind::Other { .. } => {
+                broken.push(inline);
+                continue;
+            }
+            _ => {
+                if let Some(content) = inline.kind.content_mut() {
+                    *content = hard_breaks(std::mem::take(content));
+                }
+                broken.push(inline);
+                continue;
+            }
+        };
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                let after_break = match broken.last() {
+                    Some(last) => last.kind == InlineKind::LineBreak,
+                    None => false,
+                };
+                if !after_break {
+                    broken.push(line_break());
+                }
+            }
+            let line = match i {
+                0 => line.to_owned(),
+                _ => format!("\n{}", line),
+            };
+            if !line.is_empty() {
+                broken.push(Inline::unquoted(&line));
+            }
+        }
+    }
+    broken
+}
+
+fn line_break() -> Inline {
+    Inline::new(InlineKind::LineBreak)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        block::{BlockContent, BlockContext},
+        document::Document,
+        inline::QuoteType,
+        passthroughs::Passthrough,
+        subs::{Substitution, Substitutions},
+    };
+
+    fn paragraphs(s: &str) -> Vec<Vec<Inline>> {
+        Document::parse(s)
+            .unwrap()
+            .blocks
+            .into_iter()
+            .filter_map(|b| match b.context {
+                BlockContext::Paragraph(p) => Some(p.0),
+                BlockContext::Verse(q) => Some(match q.content {
+                    BlockContent::Simple(s) => s.0,
+                    _ => unreachable!(),
+                }),
+                BlockContext::Literal(v) => Some(v.0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(
+            paragraphs("Roses are red, +\nviolets *are* blue. +\nSo +is+ this.")[0],
+            vec![
+                Inline::unquoted("Roses are red,"),
+                line_break(),
+                Inline::unquoted("\nviolets "),
+                Inline::new(InlineKind::Quoted {
+                    ty: QuoteType::Strong,
+                    content: vec![Inline::unquoted("are")]
+                }),
+                Inline::unquoted(" blue."),
+                line_break(),
+                Inline::unquoted("\nSo "),
+                Inline::new(InlineKind::Passthrough(Passthrough {
+                    subs: Substitutions(vec![Substitution::SpecialCharacters]),
+                    content: vec![Inline::unquoted("is")]
+                })),
+                Inline::unquoted(" this."),
+            ]
+        );
+        assert_eq!(
+            paragraphs("[literal]\nkeep +\nthis")[0],
+            vec![Inline::unquoted("keep +\nthis")]
+        );
+    }
+
+    #[test]
+    fn hardbreaks_option() {
+        let broken = vec![
+            Inline::unquoted("Name"),
+            line_break(),
+            Inline::unquoted("\nStreet"),
+            line_break(),
+            Inline::unquoted("\nTown"),
+        ];
+        let p = paragraphs("[%hardbreaks]\nName\nStreet +\nTown\n\n[verse]\nName\nStreet");
+        assert_eq!(p[0], broken);
+        assert_eq!(p[1], vec![Inline::unquoted("Name\nStreet")]);
+        let p = paragraphs(":hardbreaks-option:\n\nName\nStreet\nTown");
+        assert_eq!(p[0], broken);
+    }
+
+    #[test]
+    fn hardbreaks_skip_raw_text() {
+        let p = paragraphs("[%hardbreaks]\nline +++a\nb+++\nlast api:x[a\nb]");
+        assert_eq!(
+            p[0],
+            vec![
+                Inline::unquoted("line "),
+                Inline::new(InlineKind::Passthrough(Passthrough {
+                    subs: Substitutions::none(),
+                    content: vec![Inline::unquoted("a\nb")]
+                })),
+                line_break(),
+                Inline::unquoted("\nlast "),
+                Inline::new(InlineKind::Other {
+                    ty: "api".to_owned(),
+                    content: vec![Inline::unquoted("api:x[a\nb]")]
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::cell::Cell;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    inline::{Inline, InlineKind},
+    subs::{replace_all, Node},
+};
+
+/// Substitute callouts at the end of lines of verbatim content, such as `<1>`, `<!--1-->` for
+/// XML or `// <1>` after a line comment, which is dropped. `<.>` takes the next number in the
+/// block. An escaped callout, `\<1>`, is kept as text.
+pub fn substitute_callouts(nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_LINE: Regex =
+            Regex::new(r"(?m)((?://|#|--|;;) ?)?((?:\\?<(?:!--)?(?:\d+|\.)(?:--)?> ?)+)$").unwrap();
+        static ref RE: Regex = Regex::new(r"(\\)?<(!--)?(\d+|\.)(--)?>").unwrap();
+    }
+    let next = Cell::new(1);
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_LINE, |caps| {
+            let mut callouts = Vec::new();
+            for callout in RE.captures_iter(&caps[2]) {
+                if callout.get(2).is_some() != callout.get(4).is_some() {
+                    return None;
+                }
+                if callout.get(1).is_some() {
+                    callouts.push(Node::Literal(callout[0][1..].to_owned()));
+                    continue;
+                }
+                let number = match &callout[3] {
+                    "." => next.get(),
+                    n => n.parse().ok()?,
+                };
+                next.set(number + 1);
+                callouts.push(Node::Inline(callout_inline(number), Vec::new()));
+            }
+            // Keep the comment when all callouts are escaped
+            if let (Some(comment), false) = (caps.get(1), callouts.iter().any(is_callout)) {
+                callouts.insert(0, Node::Literal(comment.as_str().to_owned()));
+            }
+            Some(callouts)
+        })
+    })
+}
+
+fn is_callout(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Inline(
+            Inline {
+                kind: InlineKind::Callout(_),
+                ..
+            },
+            _
+        )
+    )
+}
+
+fn callout_inline(number: usize) -> Inline {
+    Inline::new(InlineKind::Callout(number))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{block::BlockContext, document::Document};
+
+    fn listing(s: &str) -> Vec<Inline> {
+        match Document::parse(s).unwrap().blocks.remove(0).context {
+            BlockContext::Listing(v) => v.0,
+            BlockContext::Source(s) => s.content.0,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn callouts() {
+        assert_eq!(
+            listing("----\nlet x = 1; // <1>\nlet y = 2; <2> <3>\n<tag/> <!--4-->\n----"),
+            vec![
+                Inline::unquoted("let x = 1; "),
+                callout_inline(1),
+                Inline::unquoted("\nlet y = 2; "),
+                callout_inline(2),
+                callout_inline(3),
+                Inline::unquoted("\n<tag/> "),
+                callout_inline(4),
+            ]
+        );
+        assert_eq!(
+            listing("[source,ruby]\n----\nputs 1 # <.>\nputs 2 # <.>\n----"),
+            vec![
+                Inline::unquoted("puts 1 "),
+                callout_inline(1),
+                Inline::unquoted("\nputs 2 "),
+                callout_inline(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn not_callouts() {
+        for s in [
+            "----\n// \\<1>\n----",
+            "----\nx <1> y\n----",
+            "----\n<!--1>\n----",
+            "[subs=-callouts]\n----\nx <1>\n----",
+        ] {
+            assert!(
+                listing(s)
+                    .iter()
+                    .all(|i| !matches!(i.kind, InlineKind::Callout(_))),
+                "{}",
+                s
+            );
+        }
+        assert_eq!(
+            listing("----\nx // \\<1>\n----"),
+            vec![Inline::unquoted("x // <1>")]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use super::{
+    block::{Block, BlockContext, SectionBlock},
+    diagnostic::Diagnostic,
+    document::{Doctype, DocumentAttrs},
+    inline::{Inline, InlineKind},
+    section::SectionStyle,
+    span::Span,
+};
+
+/// Targets of cross-references, by ID
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Catalog {
+    pub refs: HashMap<String, Reference>,
+    /// IDs in the order they were registered, which is document order
+    order: Vec<String>,
+}
+
+/// A target of cross-references
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// Span of the block the target is in, or of the block around an inline target
+    pub span: Span,
+    /// From the `reftext` attribute
+    pub reftext: Option<String>,
+    pub title: Option<String>,
+    /// Section number, such as `1.2` or `A`
+    pub number: Option<String>,
+    /// Kind of a numbered target, such as `Section` or `Appendix`
+    pub signifier: Option<String>,
+}
+
+impl Catalog {
+    /// Register the blocks and inlines with an ID in `blocks` and the blocks nested in them.
+    /// The first target with an ID wins, and the others are reported.
+    pub fn build(blocks: &mut [Block], attrs: &DocumentAttrs) -> (Self, Vec<Diagnostic>) {
+        let mut catalog = Self::default();
+        let mut diagnostics = Vec::new();
+        for block in blocks {
+            block.walk_mut(&mut |b| {
+                catalog.register_block(b, attrs, &mut diagnostics);
+                let span = b.span;
+                for inlines in b.inlines_mut() {
+                    Inline::walk_mut(inlines, &mut |i| {
+                        catalog.register_inline(i, span, &mut diagnostics)
+                    });
+                }
+            });
+        }
+        (catalog, diagnostics)
+    }
+
+    /// Register an inline anchor, or an inline with an ID such as `[#id]#text#`, whose
+    /// text is its reference text
+    fn register_inline(&mut self, inline: &Inline, span: Span, diagnostics: &mut Vec<Diagnostic>) {
+        let (id, reftext) = match (&inline.kind, inline.attrs.id()) {
+            (InlineKind::Anchor(anchor), _) => (anchor.id.clone(), anchor.reftext.clone()),
+            (kind, Some(id)) => {
+                let text = match kind {
+                    InlineKind::Quoted { content, .. } => Some(Inline::text(content)),
+                    _ => None,
+                };
+                (id.to_owned(), text)
+            }
+            _ => return,
+        };
+        let reference = Reference {
+            span,
+            reftext,
+            title: None,
+            number: None,
+            signifier: None,
+        };
+        self.insert(id, reference, "anchor", diagnostics);
+    }
+
+    fn register_block(
+        &mut self,
+        block: &Block,
+        attrs: &DocumentAttrs,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let id = match &block.anchor {
+            Some(id) => id.clone(),
+            None => return,
+        };
+        let kind = match &block.context {
+            BlockContext::Section(_) | BlockContext::Part(_) => "section",
+            _ => "block",
+        };
+        let (number, signifier) = match &block.context {
+            BlockContext::Section(SectionBlock {
+                level,
+                style,
+                number: Some(number),
+                ..
+            }) => {
+                let refsig = match (style, attrs.doctype(), level) {
+                    (Some(SectionStyle::Appendix), _, _) => "appendix-refsig",
+                    (_, Doctype::Book, 1) => "chapter-refsig",
+                    _ => "section-refsig",
+                };
+                (Some(number.clone()), attrs.get(refsig).map(str::to_owned))
+            }
+            _ => (None, None),
+        };
+        let reference = Reference {
+            span: block.span,
+            reftext: block
+                .attrs
+                .as_ref()
+                .and_then(|a| a.get("reftext"))
+                .map(str::to_owned),
+            title: block.title.as_deref().map(Inline::text),
+            number,
+            signifier,
+        };
+        self.insert(id, reference, kind, diagnostics);
+    }
+
+    /// Register a target of some `kind`, such as a block, or report that its ID is taken
+    fn insert(
+        &mut self,
+        id: String,
+        reference: Reference,
+        kind: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if self.refs.contains_key(&id) {
+            diagnostics.push(Diagnostic {
+                span: reference.span,
+                message: format!("id assigned to {} already in use: {}", kind, id),
+            });
+            return;
+        }
+        self.order.push(id.clone());
+        self.refs.insert(id, reference);
+    }
+
+    /// ID of the first target with `title` in document order, for references by title
+    /// like `<<Section Title>>`
+    pub fn find_by_title(&self, title: &str) -> Option<&str> {
+        self.order
+            .iter()
+            .find(|id| {
+                let r = &self.refs[*id];
+                r.reftext.as_deref() == Some(title) || r.title.as_deref() == Some(title)
+            })
+            .map(String::as_str)
+    }
+}
+
+impl Reference {
+    /// Text of a reference without text, following `xrefstyle`. The `reftext` attribute
+    /// takes precedence. `full` is like `Section 1.2, “Title”`, `short` like `Section 1.2`,
+    /// and `basic` is the title. Unnumbered targets show their title in all styles.
+    pub fn xref_text(&self, xrefstyle: Option<&str>) -> Option<String> {
+        if let Some(reftext) = &self.reftext {
+            return Some(reftext.clone());
+        }
+        let title = self.title.as_ref()?;
+        let text = match (xrefstyle, &self.number, &self.signifier) {
+            (Some("full"), Some(number), Some(signifier)) => {
+                format!("{} {}, \u{201c}{}\u{201d}", signifier, number, title)
+            }
+            (Some("short"), Some(number), Some(signifier)) => format!("{} {}", signifier, number),
+            _ => title.clone(),
+        };
+        Some(text)
+    }
+}
@@ -0,0 +1,10 @@
+use super::span::Span;
+
+/// A problem found in a document which does not stop parsing, such as a reference to a
+/// missing ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Span of the block the problem was found in
+    pub span: Span,
+    pub message: String,
+}
@@ -1,109 +1,361 @@
-use anyhow::*;
-use std::result::Result::{Err, Ok};
+use std::collections::HashMap;
 
-use std::string::ParseError;
-use std::{collections::HashMap, iter::Peekable};
+use lazy_static::lazy_static;
+use regex::Regex;
 
-use crate::ast::reader::ParserError;
-use crate::ast::reader::{
-    Parser, SectionTitle,
-};
+use crate::ast::reader::{DocumentAttrEntry, DocumentAttrValue, Parser, ParserError, SectionTitle};
 
-use super::{block::Block, inline::Inline};
+use super::{
+    block::{Block, BlockContext, SectionScope, SimpleBlock},
+    catalog::Catalog,
+    diagnostic::Diagnostic,
+    extensions::Extensions,
+    footnotes::{self, Footnote},
+    index::Index,
+    inline::Inline,
+    line_scanner::LineScanner,
+    section,
+    state::ParserState,
+    xref,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DocumentAttrs {
-    by_name: HashMap<String, Vec<Inline>>,
+    by_name: HashMap<String, String>,
+}
+
+/// Attributes set before parsing, as in Asciidoctor
+const DEFAULT_ATTRS: &[(&str, &str)] = &[
+    ("appendix-caption", "Appendix"),
+    ("appendix-refsig", "Appendix"),
+    ("chapter-refsig", "Chapter"),
+    ("doctype", "article"),
+    ("caution-caption", "Caution"),
+    ("idprefix", "_"),
+    ("idseparator", "_"),
+    ("important-caption", "Important"),
+    ("manname-title", "Name"),
+    ("note-caption", "Note"),
+    ("sectids", ""),
+    ("section-refsig", "Section"),
+    ("tip-caption", "Tip"),
+    ("warning-caption", "Warning"),
+];
+
+/// Document type, from the `doctype` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Doctype {
+    #[default]
+    Article,
+    /// Level-0 sections are parts
+    Book,
+    /// A man page, which must have a NAME section
+    Manpage,
+    /// Only the content of the first paragraph is kept
+    Inline,
+}
+
+impl Doctype {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "article" => Some(Self::Article),
+            "book" => Some(Self::Book),
+            "manpage" => Some(Self::Manpage),
+            "inline" => Some(Self::Inline),
+            _ => None,
+        }
+    }
+}
+
+impl Default for DocumentAttrs {
+    fn default() -> Self {
+        let by_name = DEFAULT_ATTRS
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Self { by_name }
+    }
+}
+
+impl DocumentAttrs {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.by_name.get(name).map(|v| &v[..])
+    }
+    /// The doctype. Unknown doctypes are articles.
+    pub fn doctype(&self) -> Doctype {
+        self.get("doctype")
+            .and_then(Doctype::from_name)
+            .unwrap_or_default()
+    }
+    pub fn is_set(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.by_name.insert(name.to_owned(), value.to_owned());
+    }
+    pub fn unset(&mut self, name: &str) {
+        self.by_name.remove(name);
+    }
+    /// Scan an attribute entry, including its continuation lines, and apply it.
+    pub fn entry(&mut self, scanner: &mut LineScanner) -> Result<(), ParserError> {
+        let entry = scanner.parse::<DocumentAttrEntry>()?;
+        if entry.unset {
+            self.unset(&entry.name);
+            return Ok(());
+        }
+        let mut value = String::new();
+        let mut next = entry.value;
+        while let Some(v) = next.take() {
+            match v {
+                DocumentAttrValue::Line(s) => value.push_str(&s),
+                DocumentAttrValue::PartialLine(s) => {
+                    value.push_str(&s);
+                    value.push(' ');
+                    next = scanner
+                        .non_empty_line()
+                        .ok()
+                        .and_then(|line| DocumentAttrValue::parse(line.trim()));
+                }
+                DocumentAttrValue::PartialLineHardBreak(s) => {
+                    value.push_str(&s);
+                    value.push_str(" +\n");
+                    next = scanner
+                        .non_empty_line()
+                        .ok()
+                        .and_then(|line| DocumentAttrValue::parse(line.trim()));
+                }
+            }
+        }
+        self.set(&entry.name, value.trim_end());
+        Ok(())
+    }
 }
 
 // The AST below
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Document {
     pub title: Option<String>,
-    // FIXME placeholder
-    pub attrs: Option<DocumentAttrs>,
+    pub doctype: Doctype,
+    pub attrs: DocumentAttrs,
     pub blocks: Vec<Block>,
+    /// Targets of cross-references
+    pub catalog: Catalog,
+    /// Footnotes in order of definition
+    pub footnotes: Vec<Footnote>,
+    /// Index terms, for a generated index
+    pub index: Index,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn parse(s: &str) -> Result<Self, ParserError> {
+        Self::parse_with(s, Extensions::default())
+    }
+    /// Parse with handlers for custom syntax
+    pub fn parse_with(s: &str, extensions: Extensions) -> Result<Self, ParserError> {
+        let mut scanner = LineScanner::new(s);
+        let mut state = ParserState {
+            extensions,
+            ..Default::default()
+        };
+        scanner.empty_lines();
+        // The first line is usually a title
+        let title = match scanner.peek_parse::<SectionTitle>() {
+            Ok(st) if st.level == 0 => {
+                scanner.non_empty_line()?;
+                Some(st.title)
+            }
+            _ => None,
+        };
+        if title.is_some() {
+            Self::header(&mut scanner, &mut state)?;
+        } else {
+            // A header without title only has attribute entries
+            while scanner.peek_parse::<DocumentAttrEntry>().is_ok() {
+                state.attrs.entry(&mut scanner)?;
+            }
+        }
+        let doctype = state.attrs.doctype();
+        let mut blocks = Block::parse_blocks(&mut scanner, &mut state, SectionScope::Document)?;
+        match doctype {
+            Doctype::Manpage => Self::manpage(title.as_deref(), &blocks, &mut state)?,
+            Doctype::Inline => blocks.truncate(1),
+            Doctype::Article | Doctype::Book => (),
+        }
+        let (catalog, mut diagnostics) = Catalog::build(&mut blocks, &state.attrs);
+        diagnostics.extend(section::check_content(&mut blocks));
+        diagnostics.extend(xref::resolve(&mut blocks, &catalog, &state.attrs));
+        let (footnotes, footnote_diagnostics) = footnotes::collect(&mut blocks);
+        diagnostics.extend(footnote_diagnostics);
+        let index = Index::build(&mut blocks);
+        Ok(Self {
+            title,
+            doctype,
+            attrs: state.attrs,
+            blocks,
+            catalog,
+            footnotes,
+            index,
+            diagnostics,
+        })
+    }
+
+    /// Inline content of the first paragraph, which is the content of an inline document
+    pub fn inline(&self) -> Option<&[Inline]> {
+        match self.blocks.first().map(|b| &b.context) {
+            Some(BlockContext::Paragraph(SimpleBlock(inlines))) => Some(inlines),
+            _ => None,
+        }
+    }
+
+    /// Set the man page attributes from the title, `name(volume)`, and the first section,
+    /// which must be the NAME section with a `name - purpose` paragraph.
+    fn manpage(
+        title: Option<&str>,
+        blocks: &[Block],
+        state: &mut ParserState,
+    ) -> Result<(), ParserError> {
+        lazy_static! {
+            static ref RE_TITLE: Regex = Regex::new(r"^(.+?) *\((.+)\)$").unwrap();
+            static ref RE_NAME: Regex = Regex::new(r"^((?s).+?) +- +((?s).+)$").unwrap();
+        }
+        let caps = title
+            .and_then(|t| RE_TITLE.captures(t))
+            .ok_or_else(|| ParserError::fail("man page title must be name(volume)"))?;
+        state.attrs.set("mantitle", &caps[1]);
+        state.attrs.set("manvolnum", &caps[2]);
+        let name_title = state
+            .attrs
+            .get("manname-title")
+            .unwrap_or("Name")
+            .to_owned();
+        let section = blocks
+            .iter()
+            .find(|b| matches!(b.context, BlockContext::Section(_)))
+            .filter(|b| {
+                b.title
+                    .as_deref()
+                    .map(Inline::text)
+                    .as_deref()
+                    .map(|t| t.eq_ignore_ascii_case(&name_title))
+                    .unwrap_or(false)
+            });
+        let paragraph = match section.map(|b| &b.context) {
+            Some(BlockContext::Section(section)) => section.blocks.first(),
+            _ => return Err(ParserError::fail("man page must start with a NAME section")),
+        };
+        let text = match paragraph.map(|b| &b.context) {
+            Some(BlockContext::Paragraph(SimpleBlock(inlines))) => Inline::text(inlines),
+            _ => return Err(ParserError::fail("NAME section must have a paragraph")),
+        };
+        let caps = RE_NAME
+            .captures(&text)
+            .ok_or_else(|| ParserError::fail("NAME section must be `name - purpose`"))?;
+        state.attrs.set("manname", caps[1].trim());
+        state.attrs.set("manpurpose", caps[2].trim());
+        Ok(())
+    }
+
+    /// The header ends at the first empty line
+    fn header(scanner: &mut LineScanner, state: &mut ParserState) -> Result<(), ParserError> {
+        while let Ok(line) = scanner.peek_non_empty_line() {
+            if DocumentAttrEntry::parse(&line).is_ok() {
+                state.attrs.entry(scanner)?;
+            } else {
+                // FIXME: author and revision lines, comments are skipped
+                scanner.non_empty_line()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Document {
+    /// The values `f` returns for the inlines of all blocks, in document order
+    pub fn find_inlines<T>(&self, mut f: impl FnMut(&Inline) -> Option<T>) -> Vec<T> {
+        let mut found = Vec::new();
+        Block::walk_inlines_mut(&mut self.blocks.clone(), &mut |inline, _| {
+            found.extend(f(inline))
+        });
+        found
+    }
 }
 
-// impl<'a> Element<'a> for Document {
-//     fn parse(s: &mut Peekable<PhysicalLineIter<'a>>) -> Result<Self, ParserError> {
-//         // The first line is usually a title
-//         let first = s.next();
-//         // Empty document
-//         if first.is_none() {
-//             return Ok(Default::default());
-//         }
-//         let first = first.unwrap();
-//         let st = SectionTitle::parse(&first.content);
-//         let title;
-//         match st {
-//             Ok(st) => {
-//                 // TODO: warnings, etc
-//                 // We found a section title
-//                 title = Some(st.title);
-//             }
-//             Err(e) => {
-//                 if e.mismatches() {
-//                     title = None;
-//                 } else {
-//                     return Err(ParserError::fail("section title parse error"));
-//                 }
-//             }
-//         }
-//         // FIXME: author
-//         // FIXME: revision
-//         // FIXME: actually parse attributes
-//         Self::skip_attributes(s);
-
-//         let blocks = Self::parse_blocks(s)?;
-
-//         Ok(Self {
-//             title,
-//             attrs: None,
-//             blocks,
-//         })
-//     }
-// }
-
-// impl<'a> Document {
-//     /// FIXME: actually parse attributes
-//     fn skip_attributes(s: &mut Peekable<PhysicalLineIter<'a>>) {
-//         // Consume until encountering an empty line
-//         while Self::empty_line(s) {}
-//     }
-
-//     fn skip_empty_lines(s: &mut Peekable<PhysicalLineIter<'a>>) {
-//         // Consume until encountering an empty line
-//         while Self::empty_line(s) {}
-//     }
-//     // Consume an empty line
-//     fn empty_line(s: &mut Peekable<PhysicalLineIter<'a>>) -> bool {
-//         if let Some(line) = s.peek() {
-//             if line.content.trim().is_empty() {
-//                 s.next();
-//                 return true;
-//             }
-//         }
-//         false
-//     }
-
-//     fn parse_blocks(s: &mut Peekable<PhysicalLineIter<'a>>) -> Result<Vec<Block>, ParserError> {
-//         let mut blocks = Vec::new();
-//         Self::skip_empty_lines(s);
-//         loop {
-//             // Loop until EOF
-//             if s.peek().is_none() {
-//                 break;
-//             }
-//             let block = Block::parse(s);
-//             match block {
-//                 Ok(block) => blocks.push(block),
-//                 Err(e) => {
-//                     // TODO: partial output
-//                 }
-//             }
-//         }
-//         Ok(blocks)
-//     }
-// }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::block::BlockStyle;
+
+    #[test]
+    fn book_parts() {
+        let doc = Document::parse(
+            "= Book\n:doctype: book\n\n= Part One\n\nIntro.\n\nMore intro.\n\n== Chapter\n\n\
+             = Part Two\n\n[partintro]\n--\nIntro.\n--\n\n== Chapter",
+        )
+        .unwrap();
+        assert_eq!(doc.doctype, Doctype::Book);
+        let parts: Vec<_> = doc
+            .blocks
+            .iter()
+            .map(|b| match &b.context {
+                BlockContext::Part(part) => part,
+                other => panic!("expected a part, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(parts.len(), 2);
+        for part in parts {
+            assert_eq!(part.blocks.len(), 2);
+            assert_eq!(part.blocks[0].style, BlockStyle::PartIntro);
+            assert!(matches!(part.blocks[1].context, BlockContext::Section(_)));
+        }
+    }
+
+    #[test]
+    fn manpage() {
+        let doc = Document::parse(
+            "= git-foo(1)\n:doctype: manpage\n\n== Name\n\ngit-foo, git-bar - do foo\nthings\n\n\
+             == Synopsis\n\n*git foo*",
+        )
+        .unwrap();
+        assert_eq!(doc.doctype, Doctype::Manpage);
+        assert_eq!(doc.attrs.get("mantitle"), Some("git-foo"));
+        assert_eq!(doc.attrs.get("manvolnum"), Some("1"));
+        assert_eq!(doc.attrs.get("manname"), Some("git-foo, git-bar"));
+        assert_eq!(doc.attrs.get("manpurpose"), Some("do foo\nthings"));
+    }
+
+    #[test]
+    fn manpage_with_markup() {
+        let doc = Document::parse(
+            "= git-foo(1)\n:doctype: manpage\n\n== _Name_\n\n`git-foo` - it's *really* foo",
+        )
+        .unwrap();
+        assert_eq!(doc.attrs.get("manname"), Some("git-foo"));
+        assert_eq!(doc.attrs.get("manpurpose"), Some("it\u{2019}s really foo"));
+    }
+
+    #[test]
+    fn invalid_manpages() {
+        let errors = [
+            "= git-foo\n:doctype: manpage\n\n== Name\n\ngit-foo - do foo",
+            "= git-foo(1)\n:doctype: manpage\n\n== Synopsis\n\ngit-foo - do foo",
+            "= git-foo(1)\n:doctype: manpage\n\n== Name\n\ngit-foo does foo",
+            "= git-foo(1)\n:doctype: manpage\n\nNo sections",
+        ];
+        for s in errors {
+            assert!(!Document::parse(s).unwrap_err().mismatches(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn inline_doctype() {
+        let doc = Document::parse(":doctype: inline\n\nFirst paragraph.\n\nSecond one.").unwrap();
+        assert_eq!(doc.blocks.len(), 1);
+        assert_eq!(
+            doc.inline(),
+            Some(&[Inline::unquoted("First paragraph.")][..])
+        );
+    }
+}
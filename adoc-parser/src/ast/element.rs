@@ -1,21 +1,11 @@
 /// This module holds data structure for the final AST.
 /// Intermediate syntax constructs and the parser are not included.
-use std::{collections::HashMap, iter::Peekable};
-
-use super::reader::ParserError;
+use std::collections::HashMap;
 
 use super::reader;
 
-/// Documents, Blocks, and Inline are all elements
-// pub trait Element<'a> {
-//     /// TODO: better error handling
-//     fn parse(lines: &mut Peekable<PhysicalLineIter<'a>>) -> Result<Self, ParserError>
-//     where
-//         Self: Sized;
-// }
-
 /// Attribute map for elements
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ElementAttrs {
     by_name: HashMap<ElementName, ElementAttr>,
     by_index: Vec<ElementAttr>,
@@ -24,7 +14,7 @@ pub struct ElementAttrs {
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct ElementName(String);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ElementAttr(String);
 
 impl From<reader::ElementAttrs> for ElementAttrs {
@@ -34,13 +24,117 @@ impl From<reader::ElementAttrs> for ElementAttrs {
         for attr in attrs.0 {
             match attr {
                 super::reader::ElementAttr::Positional(a) => {
-                    by_index.push(a);
+                    by_index.push(ElementAttr(a));
                 }
                 super::reader::ElementAttr::Named(n, v) => {
                     by_name.insert(ElementName(n), ElementAttr(v));
                 }
             }
         }
-        todo!()
+        Self { by_name, by_index }
+    }
+}
+
+impl ElementAttrs {
+    /// Named attribute
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.by_name
+            .get(&ElementName(name.to_owned()))
+            .map(|a| &a.0[..])
+    }
+    /// Positional attribute, zero-indexed. The first one is the style.
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.by_index.get(index).map(|a| &a.0[..])
+    }
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.by_name
+            .insert(ElementName(name.to_owned()), ElementAttr(value.to_owned()));
+    }
+    /// The style, with shorthands removed
+    pub fn style(&self) -> Option<&str> {
+        self.positional(0).filter(|s| !s.is_empty())
+    }
+    pub fn id(&self) -> Option<&str> {
+        self.get("id")
+    }
+    pub fn roles(&self) -> Vec<&str> {
+        self.get("role")
+            .map(|r| r.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+    /// Options from `%name`, `options=`/`opts=`, and `name-option` attributes
+    pub fn has_option(&self, name: &str) -> bool {
+        let listed = |attr| {
+            self.get(attr)
+                .map(|v| v.split(',').any(|o| o.trim() == name))
+                .unwrap_or(false)
+        };
+        listed("options") || listed("opts") || self.get(&format!("{}-option", name)).is_some()
+    }
+    /// Merge attributes of a later attribute line into this one. Later values win.
+    pub fn merge(&mut self, other: ElementAttrs) {
+        for (i, attr) in other.by_index.into_iter().enumerate() {
+            if i < self.by_index.len() {
+                self.by_index[i] = attr;
+            } else {
+                self.by_index.push(attr);
+            }
+        }
+        self.by_name.extend(other.by_name);
+    }
+    /// Add the named attributes of `other` which are not set here
+    pub fn inherit(&mut self, other: &ElementAttrs) {
+        for (name, value) in other.by_name.iter() {
+            self.by_name
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+    /// Split the first positional attribute, `style#id.role%option`, into its parts.
+    ///
+    /// Only block attribute lines and formatted text use the shorthand.
+    pub fn with_shorthand(mut self) -> Self {
+        let first = match self.by_index.first() {
+            Some(first) => first.0.clone(),
+            None => return self,
+        };
+        let mut parts = Vec::new();
+        let mut marker = None;
+        let mut current = String::new();
+        for c in first.chars() {
+            if "#.%".contains(c) {
+                parts.push((marker, std::mem::take(&mut current)));
+                marker = Some(c);
+            } else {
+                current.push(c);
+            }
+        }
+        parts.push((marker, current));
+        let mut style = String::new();
+        let mut roles = Vec::new();
+        let mut options = Vec::new();
+        for (marker, value) in parts {
+            match marker {
+                None => style = value,
+                Some('#') => self.insert("id", &value),
+                Some('.') => roles.push(value),
+                Some('%') => options.push(value),
+                _ => unreachable!(),
+            }
+        }
+        if !roles.is_empty() {
+            if let Some(role) = self.get("role") {
+                roles.insert(0, role.to_owned());
+            }
+            self.insert("role", &roles.join(" "));
+        }
+        if !options.is_empty() {
+            if let Some(opts) = self.get("options").or_else(|| self.get("opts")) {
+                options.insert(0, opts.to_owned());
+            }
+            self.insert("options", &options.join(","));
+        }
+        self.by_index[0] = ElementAttr(style);
+        self
     }
 }
@@ -0,0 +1,274 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    block::{Block, BlockContext},
+    element::ElementAttrs,
+    inline::{Inline, InlineKind},
+    macros,
+    state::ParserState,
+    subs::{replace_all, Node, Stashed},
+};
+
+/// Handler of an inline macro, `name:target[attrs]`. It gets the target and the attributes
+/// in the brackets, and returns the inlines replacing the macro. For passthrough content,
+/// return an [`InlineKind::Passthrough`].
+pub type InlineMacroHandler = dyn Fn(&str, &ElementAttrs) -> Vec<Inline>;
+
+/// Handler of a block macro, `name::target[attrs]`. It gets the target and the attributes
+/// of the block, and returns the block replacing the macro, such as a
+/// [`BlockContext::Passthrough`].
+pub type BlockMacroHandler = dyn Fn(&str, &ElementAttrs) -> BlockContext;
+
+/// Processor of blocks with a custom style, such as `[plantuml]`. It gets the lines of the
+/// paragraph or delimited block, its attributes and the state of the document, and returns
+/// the blocks replacing it.
+pub type BlockProcessor = dyn Fn(&[String], &ElementAttrs, &ParserState) -> Vec<Block>;
+
+/// Handlers of custom syntax, registered before parsing
+#[derive(Clone, Default)]
+pub struct Extensions {
+    inline_macros: HashMap<String, Rc<InlineMacroHandler>>,
+    block_macros: HashMap<String, Rc<BlockMacroHandler>>,
+    block_processors: HashMap<String, Rc<BlockProcessor>>,
+}
+
+impl Extensions {
+    /// Register a handler for the inline macro `name`
+    pub fn inline_macro<F>(&mut self, name: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&str, &ElementAttrs) -> Vec<Inline> + 'static,
+    {
+        self.inline_macros.insert(name.to_owned(), Rc::new(handler));
+        self
+    }
+    /// Register a handler for the block macro `name`. Built-in block macros, such as
+    /// `image::`, can't be replaced.
+    pub fn block_macro<F>(&mut self, name: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&str, &ElementAttrs) -> BlockContext + 'static,
+    {
+        self.block_macros.insert(name.to_owned(), Rc::new(handler));
+        self
+    }
+    /// Register a processor for blocks with the style `name`
+    pub fn block_processor<F>(&mut self, name: &str, processor: F) -> &mut Self
+    where
+        F: Fn(&[String], &ElementAttrs, &ParserState) -> Vec<Block> + 'static,
+    {
+        self.block_processors
+            .insert(name.to_owned(), Rc::new(processor));
+        self
+    }
+    pub fn find_block_macro(&self, name: &str) -> Option<&BlockMacroHandler> {
+        self.block_macros.get(name).map(|h| h.as_ref())
+    }
+    pub fn find_block_processor(&self, name: &str) -> Option<Rc<BlockProcessor>> {
+        self.block_processors.get(name).cloned()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("inline_macros", &self.inline_macros.keys())
+            .field("block_macros", &self.block_macros.keys())
+            .field("block_processors", &self.block_processors.keys())
+            .finish()
+    }
+}
+
+/// Names of built-in inline macros. Those left, such as UI macros when they are not enabled,
+/// stay text.
+const BUILT_IN: &[&str] = &[
+    "anchor",
+    "asciimath",
+    "btn",
+    "footnote",
+    "ftp",
+    "http",
+    "https",
+    "icon",
+    "image",
+    "indexterm",
+    "indexterm2",
+    "irc",
+    "kbd",
+    "latexmath",
+    "link",
+    "mailto",
+    "menu",
+    "pass",
+    "stem",
+    "xref",
+];
+
+/// Substitute the remaining inline macros in text nodes, after the built-in ones. Macros
+/// without a handler are kept as [`InlineKind::Other`], with the macro as text, when they
+/// have a target. A name after a colon, as in `std::vec::Vec[T]`, is not a macro. Targets
+/// and text are taken as they are written.
+pub fn substitute_custom_macros(nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"(\\)?\b([a-zA-Z][\w\-]*):([^\s\[]*)\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE, |caps| {
+            let name = &caps[2];
+            if BUILT_IN.contains(&name) || after_colon(s, caps.get(0).unwrap().start()) {
+                return None;
+            }
+            if caps.get(1).is_some() {
+                return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+            }
+            let target = s.source(&caps[3]);
+            let handler = match state.extensions.inline_macros.get(name) {
+                Some(handler) => handler,
+                None if target.is_empty() || target.starts_with(':') => return None,
+                None => {
+                    let inline = Inline::new(InlineKind::Other {
+                        ty: name.to_owned(),
+                        content: Vec::new(),
+                    });
+                    let raw = Node::Literal(s.source(&caps[0]));
+                    return Some(vec![Node::Inline(inline, vec![raw])]);
+                }
+            };
+            let attrs = macros::macro_attrs(&s.text(&caps[4]));
+            Some(
+                handler(&target, &attrs)
+                    .into_iter()
+                    .map(Node::from)
+                    .collect(),
+            )
+        })
+    })
+}
+
+/// Whether the text before `start` ends with a colon
+fn after_colon(s: &Stashed, start: usize) -> bool {
+    s[..start].ends_with(':')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        block::{BlockMetadata, SectionScope, SimpleBlock},
+        document::Document,
+        replacements::substitute_replacements,
+        span::Position,
+        subs::apply_with,
+    };
+
+    fn extensions() -> Extensions {
+        let mut extensions = Extensions::default();
+        extensions
+            .inline_macro("jira", |target, attrs| {
+                let text = attrs.positional(0).unwrap_or(target);
+                vec![Inline::unquoted(&format!("[{}]", text))]
+            })
+            .block_macro("toc", |target, _| {
+                BlockContext::Passthrough(format!("<nav>{}</nav>", target))
+            });
+        extensions
+    }
+
+    #[test]
+    fn inline_macros() {
+        let state = ParserState {
+            extensions: extensions(),
+            ..Default::default()
+        };
+        let inlines = apply_with(
+            "See jira:ABC-123[] and jira:ABC-7[*the bug*], not api:Type#method[] or kbd:[F1].",
+            |nodes| substitute_custom_macros(nodes, &state),
+        );
+        assert_eq!(
+            inlines,
+            vec![
+                Inline::unquoted("See [ABC-123] and [*the bug*], not "),
+                Inline::new(InlineKind::Other {
+                    ty: "api".to_owned(),
+                    content: vec![Inline::unquoted("api:Type#method[]")]
+                }),
+                Inline::unquoted(" or kbd:[F1].")
+            ]
+        );
+    }
+
+    #[test]
+    fn prose_and_replacements() {
+        let state = ParserState {
+            extensions: extensions(),
+            ..Default::default()
+        };
+        let inlines = apply_with(
+            "Use std::vec::Vec[T]. Note:[1] and a:[b] jira:AB--1[] api:it's[x...]",
+            |nodes| substitute_custom_macros(substitute_replacements(nodes), &state),
+        );
+        assert_eq!(
+            inlines[0],
+            Inline::unquoted("Use std::vec::Vec[T]. Note:[1] and a:[b] [AB--1] ")
+        );
+        assert_eq!(
+            inlines[1],
+            Inline::new(InlineKind::Other {
+                ty: "api".to_owned(),
+                content: vec![Inline::unquoted("api:it's[x...]")]
+            })
+        );
+        assert_eq!(inlines.len(), 2);
+    }
+
+    #[test]
+    fn block_macros() {
+        let doc = Document::parse_with("toc::main[]\n\nunknown::x[]", extensions()).unwrap();
+        assert_eq!(
+            doc.blocks[0].context,
+            BlockContext::Passthrough("<nav>main</nav>".to_owned())
+        );
+        assert!(matches!(doc.blocks[1].context, BlockContext::Paragraph(_)));
+    }
+
+    #[test]
+    fn block_processors() {
+        let mut extensions = Extensions::default();
+        extensions
+            .block_processor("shout", |lines, _, state| {
+                let lines: Vec<_> = lines.iter().map(|l| l.to_uppercase()).collect();
+                let mut state = state.nested();
+                Block::parse_lines(&lines, Position::new(1, 1), &mut state, SectionScope::None)
+                    .unwrap()
+            })
+            .block_processor("plantuml", |lines, attrs, _| {
+                let source = format!("{} {}", attrs.positional(1).unwrap_or("svg"), lines.len());
+                let context = BlockContext::Passthrough(source);
+                vec![BlockMetadata::default().into_block(context, Default::default())]
+            });
+        let doc = Document::parse_with(
+            "[shout]\nHello {product}.\n\n[shout]\n--\nOne.\n\nTwo.\n--\n\n\
+             .Diagram\n[plantuml#flow, png]\n....\nA -> B\nB -> C\n....\n\n\
+             [shout]\n|===\n|a\n|===",
+            extensions,
+        )
+        .unwrap();
+        let blocks: Vec<_> = doc.blocks.iter().map(|b| &b.context).collect();
+        assert_eq!(
+            &blocks[..4],
+            &[
+                &BlockContext::Paragraph(SimpleBlock(vec![Inline::unquoted("HELLO {PRODUCT}.")])),
+                &BlockContext::Paragraph(SimpleBlock(vec![Inline::unquoted("ONE.")])),
+                &BlockContext::Paragraph(SimpleBlock(vec![Inline::unquoted("TWO.")])),
+                &BlockContext::Passthrough("png 2".to_owned()),
+            ]
+        );
+        assert!(matches!(blocks[4], BlockContext::Table(_)));
+        assert_eq!(doc.blocks[1].span, doc.blocks[2].span);
+        assert_eq!(doc.blocks[3].span.start.line, 13);
+        assert_eq!(doc.blocks[3].anchor.as_deref(), Some("flow"));
+        assert_eq!(doc.blocks[3].title, Some(vec![Inline::unquoted("Diagram")]));
+    }
+}
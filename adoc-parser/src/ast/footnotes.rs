@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    block::Block,
+    diagnostic::Diagnostic,
+    inline::{Inline, InlineKind},
+    macros,
+    subs::{replace_all, Node},
+};
+
+/// A footnote macro, `footnote:[text]` or `footnote:id[text]`, which defines a footnote, or
+/// `footnote:id[]`, which refers to the footnote defined with `id`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FootnoteRef {
+    pub id: Option<String>,
+    /// Number of the footnote, set when footnotes are collected
+    pub number: Option<usize>,
+    /// Text of a definition. References have none.
+    pub content: Vec<Inline>,
+}
+
+/// A footnote in the document's list of footnotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Footnote {
+    /// Numbered from 1, in order of definition
+    pub number: usize,
+    pub id: Option<String>,
+    pub content: Vec<Inline>,
+}
+
+/// Substitute footnote macros in text nodes
+pub fn substitute_footnotes(nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"(\\)?footnote:([\w\-]+)?\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE, |caps| {
+            if caps.get(1).is_some() {
+                return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+            }
+            let inline = Inline::new(InlineKind::Footnote(FootnoteRef {
+                id: caps.get(2).map(|id| id.as_str().to_owned()),
+                number: None,
+                content: Vec::new(),
+            }));
+            let text = macros::unescape(caps[3].trim());
+            let content = match text.is_empty() {
+                true => Vec::new(),
+                false => vec![Node::Text(text)],
+            };
+            Some(vec![Node::Inline(inline, content)])
+        })
+    })
+}
+
+/// Number the footnotes in `blocks` in document order, and collect their definitions. A
+/// footnote with the ID of an earlier one refers to it, even if it has text. References to
+/// undefined IDs are reported.
+pub fn collect(blocks: &mut [Block]) -> (Vec<Footnote>, Vec<Diagnostic>) {
+    let mut footnotes: Vec<Footnote> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut diagnostics = Vec::new();
+    Block::walk_inlines_mut(blocks, &mut |inline, span| {
+        let footnote = match &mut inline.kind {
+            InlineKind::Footnote(footnote) => footnote,
+            _ => return,
+        };
+        if let Some(number) = footnote.id.as_ref().and_then(|id| ids.get(id)) {
+            footnote.number = Some(*number);
+            return;
+        }
+        if footnote.content.is_empty() {
+            let id = footnote.id.as_deref().unwrap_or_default();
+            diagnostics.push(Diagnostic {
+                span,
+                message: format!("invalid footnote reference: {}", id),
+            });
+            return;
+        }
+        let number = footnotes.len() + 1;
+        if let Some(id) = &footnote.id {
+            ids.insert(id.clone(), number);
+        }
+        footnote.number = Some(number);
+        footnotes.push(Footnote {
+            number,
+            id: footnote.id.clone(),
+            content: footnote.content.clone(),
+        });
+    });
+    (footnotes, diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{document::Document, subs::apply_with};
+
+    #[test]
+    fn footnote_forms() {
+        let inlines = apply_with(
+            "a footnote:[One.] b footnote:two[Two *2*.] c footnote:two[] \\footnote:[no]",
+            substitute_footnotes,
+        );
+        let footnotes: Vec<&FootnoteRef> = inlines
+            .iter()
+            .filter_map(|i| match &i.kind {
+                InlineKind::Footnote(f) => Some(f),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(footnotes.len(), 3);
+        assert_eq!(footnotes[0].id, None);
+        assert_eq!(footnotes[0].content, vec![Inline::unquoted("One.")]);
+        assert_eq!(footnotes[1].id.as_deref(), Some("two"));
+        assert!(footnotes[2].content.is_empty());
+        assert_eq!(inlines.last(), Some(&Inline::unquoted(" footnote:[no]")));
+    }
+
+    #[test]
+    fn footnote_numbers() {
+        let doc = Document::parse(
+            "A footnote:disclaimer[Opinions are my own.] claim.\n\n\
+             * More footnote:[Second.] and footnote:disclaimer[] again.\n\n\
+             Undefined footnote:nope[].",
+        )
+        .unwrap();
+        let numbers: Vec<_> = doc.footnotes.iter().map(|f| f.number).collect();
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(doc.footnotes[0].id.as_deref(), Some("disclaimer"));
+        assert_eq!(doc.footnotes[1].content, vec![Inline::unquoted("Second.")]);
+        let refs = doc.find_inlines(|i| match &i.kind {
+            InlineKind::Footnote(f) => Some(f.number),
+            _ => None,
+        });
+        assert_eq!(refs, vec![Some(1), Some(2), Some(1), None]);
+        assert_eq!(doc.diagnostics.len(), 1);
+        assert_eq!(
+            doc.diagnostics[0].message,
+            "invalid footnote reference: nope"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    block::{Block, BlockContext},
+    inline::{Inline, InlineKind},
+    macros,
+    span::Span,
+    subs::{replace_all, Node},
+};
+
+/// An index term. A visible term, `((primary))` or `indexterm2:[primary]`, is also shown
+/// in the text. A concealed term, `(((primary, secondary, tertiary)))` or
+/// `indexterm:[primary, secondary, tertiary]`, is not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexTerm {
+    /// The primary term, then up to two nested terms
+    pub terms: Vec<String>,
+    pub visible: bool,
+}
+
+/// Index terms of a document, grouped by first letter
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Index {
+    pub groups: Vec<IndexGroup>,
+}
+
+/// Terms starting with a letter. Terms starting with anything else are in the `@` group,
+/// which comes first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexGroup {
+    pub letter: String,
+    pub entries: Vec<IndexEntry>,
+}
+
+/// A term and the terms nested in it, sorted ignoring case
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub term: String,
+    /// Where the term is used, in document order
+    pub refs: Vec<IndexRef>,
+    pub entries: Vec<IndexEntry>,
+}
+
+/// A use of an index term, and the section it is in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexRef {
+    /// ID of the section, or `None` outside of sections
+    pub section: Option<String>,
+    /// Title of the section
+    pub title: Option<String>,
+    /// Span of the block using the term
+    pub span: Span,
+}
+
+/// Substitute index terms in text nodes. Terms are plain text, without markup.
+pub fn substitute_index_terms(mut nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_CONCEALED: Regex = Regex::new(r"(\\)?\(\(\(([^\n]+?)\)\)\)").unwrap();
+        static ref RE_VISIBLE: Regex = Regex::new(r"(\\)?\(\(([^\s(][^\n]*?)\)\)").unwrap();
+        static ref RE_MACRO: Regex =
+            Regex::new(r"(\\)?indexterm(2)?:\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_CONCEALED, |caps| {
+            index_term(caps, terms(&s.text(&caps[2])), false)
+        })
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_VISIBLE, |caps| {
+            index_term(caps, vec![s.text(caps[2].trim())], true)
+        })
+    });
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MACRO, |caps| match caps.get(2) {
+            Some(_) => index_term(caps, vec![macros::unescape(&s.text(caps[3].trim()))], true),
+            None => index_term(caps, terms(&s.text(&caps[3])), false),
+        })
+    })
+}
+
+/// Terms separated by commas. Terms may be quoted.
+fn terms(s: &str) -> Vec<String> {
+    let attrs = macros::macro_attrs(s);
+    (0..3)
+        .map_while(|i| attrs.positional(i))
+        .map(|t| t.trim().to_owned())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn index_term(caps: &Captures, terms: Vec<String>, visible: bool) -> Option<Vec<Node>> {
+    if caps.get(1).is_some() {
+        return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+    }
+    if terms.is_empty() || terms[0].is_empty() {
+        return None;
+    }
+    let inline = Inline::new(InlineKind::IndexTerm(IndexTerm { terms, visible }));
+    Some(vec![Node::Inline(inline, Vec::new())])
+}
+
+impl Index {
+    /// Collect the index terms in `blocks`, with the sections they are used in
+    pub fn build(blocks: &mut [Block]) -> Self {
+        let mut refs = Vec::new();
+        for block in blocks {
+            Self::visit(block, None, &mut refs);
+        }
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        for (terms, r) in refs {
+            let mut level = &mut entries;
+            let mut terms = terms.into_iter().peekable();
+            while let Some(term) = terms.next() {
+                let i = match level.iter().position(|e| e.term == term) {
+                    Some(i) => i,
+                    None => {
+                        level.push(IndexEntry {
+                            term,
+                            refs: Vec::new(),
+                            entries: Vec::new(),
+                        });
+                        level.len() - 1
+                    }
+                };
+                if terms.peek().is_none() {
+                    level[i].refs.push(r.clone());
+                }
+                level = &mut level[i].entries;
+            }
+        }
+        IndexEntry::sort(&mut entries);
+        let mut groups: Vec<IndexGroup> = Vec::new();
+        for entry in entries {
+            let letter = match entry.term.chars().next() {
+                Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+                _ => "@".to_owned(),
+            };
+            match groups.iter_mut().find(|g| g.letter == letter) {
+                Some(group) => group.entries.push(entry),
+                None => groups.push(IndexGroup {
+                    letter,
+                    entries: vec![entry],
+                }),
+            }
+        }
+        groups.sort_by(|a, b| a.letter.cmp(&b.letter));
+        Self { groups }
+    }
+
+    fn visit(
+        block: &mut Block,
+        section: Option<&IndexRef>,
+        refs: &mut Vec<(Vec<String>, IndexRef)>,
+    ) {
+        let current = match &block.context {
+            BlockContext::Section(_) | BlockContext::Part(_) => Some(IndexRef {
+                section: block.anchor.clone(),
+                title: block.title.as_deref().map(Inline::text),
+                span: block.span,
+            }),
+            _ => section.cloned(),
+        };
+        let span = block.span;
+        for inlines in block.inlines_mut() {
+            Inline::walk_mut(inlines, &mut |inline| {
+                if let InlineKind::IndexTerm(term) = &inline.kind {
+                    let r = IndexRef {
+                        section: current.as_ref().and_then(|s| s.section.clone()),
+                        title: current.as_ref().and_then(|s| s.title.clone()),
+                        span,
+                    };
+                    refs.push((term.terms.clone(), r));
+                }
+            });
+        }
+        for child in block.children_mut() {
+            Self::visit(child, current.as_ref(), refs);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+impl IndexEntry {
+    fn sort(entries: &mut [IndexEntry]) {
+        entries.sort_by_key(|e| e.term.to_lowercase());
+        for entry in entries {
+            Self::sort(&mut entry.entries);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        document::Document, quotes::substitute_quotes, replacements::substitute_replacements,
+        subs::apply_with,
+    };
+
+    fn term(terms: &[&str], visible: bool) -> Inline {
+        Inline::new(InlineKind::IndexTerm(IndexTerm {
+            terms: terms.iter().map(|t| t.to_string()).collect(),
+            visible,
+        }))
+    }
+
+    #[test]
+    fn index_term_forms() {
+        assert_eq!(
+            apply_with(
+                "((Rust)) (((cargo, build, \"release, debug\"))) \
+                 indexterm:[crates, io] indexterm2:[serde] \\((no))",
+                substitute_index_terms
+            ),
+            vec![
+                term(&["Rust"], true),
+                Inline::unquoted(" "),
+                term(&["cargo", "build", "release, debug"], false),
+                Inline::unquoted(" "),
+                term(&["crates", "io"], false),
+                Inline::unquoted(" "),
+                term(&["serde"], true),
+                Inline::unquoted(" ((no))"),
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_text_terms() {
+        assert_eq!(
+            apply_with(
+                "((it's)) (((*a*, b...))) indexterm:[x--y] indexterm2:[_it's_]",
+                |nodes| substitute_index_terms(substitute_replacements(substitute_quotes(nodes)))
+            ),
+            vec![
+                term(&["it\u{2019}s"], true),
+                Inline::unquoted(" "),
+                term(&["a", "b\u{2026}"], false),
+                Inline::unquoted(" "),
+                term(&["x\u{2014}\u{200b}y"], false),
+                Inline::unquoted(" "),
+                term(&["it\u{2019}s"], true),
+            ]
+        );
+    }
+
+    #[test]
+    fn index_catalog() {
+        let doc = Document::parse(
+            "Intro (((zebra))).\n\n== Tools\n\nUse ((cargo)) and (((cargo, build))).\n\n\
+             == More\n\n* (((Apple))) ((cargo)) indexterm:[1st]\n\n[index]\n== Index",
+        )
+        .unwrap();
+        let letters: Vec<_> = doc.index.groups.iter().map(|g| &g.letter[..]).collect();
+        assert_eq!(letters, vec!["@", "A", "C", "Z"]);
+        let cargo = &doc.index.groups[2].entries[0];
+        assert_eq!(cargo.term, "cargo");
+        let sections: Vec<_> = cargo.refs.iter().map(|r| r.section.as_deref()).collect();
+        assert_eq!(sections, vec![Some("_tools"), Some("_more")]);
+        assert_eq!(cargo.refs[0].title.as_deref(), Some("Tools"));
+        assert_eq!(cargo.entries[0].term, "build");
+        assert_eq!(cargo.entries[0].refs.len(), 1);
+        assert_eq!(doc.index.groups[3].entries[0].refs[0].section, None);
+    }
+}
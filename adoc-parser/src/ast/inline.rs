@@ -1,24 +1,53 @@
-use std::iter::Peekable;
+use super::{
+    anchors::Anchor,
+    element::ElementAttrs,
+    footnotes::FootnoteRef,
+    index::IndexTerm,
+    links::Link,
+    math::Math,
+    media::{Icon, ImageBlock},
+    passthroughs::Passthrough,
+    replacements::Replacement,
+    ui::UiMacro,
+    xref::Xref,
+};
 
-use super::reader::*;
-
-use super::element::ElementAttrs;
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Inline {
     pub attrs: ElementAttrs,
     pub kind: InlineKind,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InlineKind {
     /// Leaf kind
-    Unquoted,
+    Unquoted(String),
     /// Emphasis, strong, etc
     Quoted {
         ty: QuoteType,
         content: Vec<Inline>,
     },
+    /// Typographic replacement, such as an em dash
+    Replacement(Replacement),
+    Anchor(Anchor),
+    /// Callout number at the end of a line of verbatim content, `<1>`
+    Callout(usize),
+    Footnote(FootnoteRef),
+    Icon(Icon),
+    /// Inline image
+    Image(Box<ImageBlock>),
+    IndexTerm(IndexTerm),
+    /// Hard line break, ` +` at the end of a line
+    LineBreak,
+    Link(Link),
+    /// `stem:[source]`, an inline formula
+    Math(Math),
+    /// `+text+`, `+++text+++` or `pass:[text]`, content which only gets the substitutions
+    /// of the passthrough
+    Passthrough(Passthrough),
+    /// Keyboard, button and menu macros
+    Ui(UiMacro),
+    Xref(Xref),
     Other {
         ty: String,
         content: Vec<Inline>,
@@ -38,7 +67,7 @@ pub enum QuoteType {
     Monospaced,
     /// `__emphasis__` and `_emphasis_`
     Emphasis,
-    /// `##mark##` and `#mark#`
+    /// `##mark##` and `#mark#`. With attributes, `[.role]#text#`, a span instead of marked text
     Mark,
     /// `^superscript^`, unconstrained
     Superscript,
@@ -46,8 +75,82 @@ pub enum QuoteType {
     Subscript,
 }
 
-// impl<'a> Element<'a> for Inline {
-//     fn parse(s: &mut Peekable<PhysicalLineIter<'a>>) -> Result<Self, ParserError> {
-//         todo!()
-//     }
-// }
+impl InlineKind {
+    /// Inlines nested in this one
+    pub fn content_mut(&mut self) -> Option<&mut Vec<Inline>> {
+        match self {
+            Self::Unquoted(_)
+            | Self::Replacement(_)
+            | Self::Anchor(_)
+            | Self::Callout(_)
+            | Self::Icon(_)
+            | Self::Image(_)
+            | Self::IndexTerm(_)
+            | Self::LineBreak
+            | Self::Math(_)
+            | Self::Ui(_) => None,
+            Self::Quoted { content, .. }
+            | Self::Footnote(FootnoteRef { content, .. })
+            | Self::Link(Link { content, .. })
+            | Self::Passthrough(Passthrough { content, .. })
+            | Self::Xref(Xref { content, .. })
+            | Self::Other { content, .. } => Some(content),
+        }
+    }
+}
+
+impl Inline {
+    /// An inline without attributes
+    pub fn new(kind: InlineKind) -> Self {
+        Self {
+            attrs: Default::default(),
+            kind,
+        }
+    }
+    /// Plain text without attributes
+    pub fn unquoted(s: &str) -> Self {
+        Self::new(InlineKind::Unquoted(s.to_owned()))
+    }
+}
+
+/// Traversal
+impl Inline {
+    /// Visit each inline and the inlines nested in it, depth first
+    pub fn walk_mut<F: FnMut(&mut Inline)>(inlines: &mut [Inline], f: &mut F) {
+        for inline in inlines {
+            f(inline);
+            if let Some(content) = inline.kind.content_mut() {
+                Self::walk_mut(content, f);
+            }
+        }
+    }
+
+    /// Plain text of inlines, without markup
+    pub fn text(inlines: &[Inline]) -> String {
+        inlines
+            .iter()
+            .map(|inline| match &inline.kind {
+                InlineKind::Unquoted(s) => s.clone(),
+                InlineKind::Replacement(r) => r.text().to_owned(),
+                InlineKind::Image(image) => image.alt.clone(),
+                InlineKind::IndexTerm(IndexTerm {
+                    terms,
+                    visible: true,
+                }) => terms[0].clone(),
+                InlineKind::Math(math) => math.source.clone(),
+                InlineKind::Anchor(_)
+                | InlineKind::Callout(_)
+                | InlineKind::Footnote(_)
+                | InlineKind::IndexTerm(_)
+                | InlineKind::LineBreak
+                | InlineKind::Icon(_)
+                | InlineKind::Ui(_) => String::new(),
+                InlineKind::Quoted { content, .. }
+                | InlineKind::Link(Link { content, .. })
+                | InlineKind::Passthrough(Passthrough { content, .. })
+                | InlineKind::Xref(Xref { content, .. })
+                | InlineKind::Other { content, .. } => Self::text(content),
+            })
+            .collect()
+    }
+}
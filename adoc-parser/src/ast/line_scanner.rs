@@ -1,15 +1,16 @@
 use std::str::Lines;
 
-use itertools::{MultiPeek, PeekNth};
+use itertools::PeekNth;
 
 use super::{
-    element::{ElementAttr, ElementAttrs},
-    reader::{self, Delimiter, Parser, ParserError},
+    element::ElementAttrs,
+    reader::{self, BlockAnchor, Delimiter, ListContinuation, ListItemLine, Parser, ParserError},
+    span::Position,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum PhysicalLine<'a> {
-    EOF,
+    Eof,
     /// An empty line contains nothing or only whitespace
     Empty,
     Line(&'a str),
@@ -18,7 +19,7 @@ enum PhysicalLine<'a> {
 impl<'a> From<Option<&'a str>> for PhysicalLine<'a> {
     fn from(s: Option<&'a str>) -> Self {
         match s {
-            None => PhysicalLine::EOF,
+            None => PhysicalLine::Eof,
             Some(s) => {
                 if s.trim().is_empty() {
                     PhysicalLine::Empty
@@ -37,7 +38,7 @@ impl<'a> PhysicalLine<'a> {
         F: Fn(&'a str) -> Result<B, ParserError>,
     {
         match self {
-            PhysicalLine::EOF | PhysicalLine::Empty => Err(ParserError::Mismatch),
+            PhysicalLine::Eof | PhysicalLine::Empty => Err(ParserError::Mismatch),
             PhysicalLine::Line(s) => f(s),
         }
     }
@@ -47,7 +48,7 @@ impl<'a> PhysicalLine<'a> {
         F: Fn(&'a str) -> B,
     {
         match self {
-            PhysicalLine::EOF | PhysicalLine::Empty => Err(ParserError::Mismatch),
+            PhysicalLine::Eof | PhysicalLine::Empty => Err(ParserError::Mismatch),
             PhysicalLine::Line(s) => Ok(f(s)),
         }
     }
@@ -56,19 +57,66 @@ impl<'a> PhysicalLine<'a> {
 /// A LL(N) scanner which operates on lines
 pub struct LineScanner<'a> {
     inner: PeekNth<Lines<'a>>,
+    /// Position of the first line in the enclosing file
+    origin: Position,
+    /// Number of lines consumed
+    consumed: usize,
+    /// Length of the last consumed line
+    last_len: usize,
+}
+
+impl<'a> LineScanner<'a> {
+    pub fn new(s: &'a str) -> Self {
+        Self::with_origin(s, Position::default())
+    }
+    /// A scanner for text embedded in a file, such as the content of a table cell
+    pub fn with_origin(s: &'a str, origin: Position) -> Self {
+        Self {
+            inner: itertools::peek_nth(s.lines()),
+            origin,
+            consumed: 0,
+            last_len: 0,
+        }
+    }
+    /// Position of the start of the next line
+    pub fn position(&self) -> Position {
+        self.position_at(self.consumed, 0)
+    }
+    /// Position right after the last consumed line
+    pub fn end_position(&self) -> Position {
+        match self.consumed {
+            0 => self.origin,
+            n => self.position_at(n - 1, self.last_len),
+        }
+    }
+    fn position_at(&self, line: usize, column: usize) -> Position {
+        match line {
+            0 => Position::new(self.origin.line, self.origin.column + column),
+            _ => Position::new(self.origin.line + line, 1 + column),
+        }
+    }
 }
 
 /// Token iterators
 impl<'a> LineScanner<'a> {
     /// Returns one physical line
-    fn next(&mut self) -> PhysicalLine {
-        self.inner.next().into()
+    fn next(&mut self) -> PhysicalLine<'a> {
+        self.next_raw().into()
     }
-    fn peek(&mut self) -> PhysicalLine {
-        self.inner.peek().map(|s| *s).into()
+    /// Returns one physical line as it is, even if it only contains whitespace
+    fn next_raw(&mut self) -> Option<&'a str> {
+        let line = self.inner.next();
+        if let Some(line) = line {
+            self.consumed += 1;
+            self.last_len = line.chars().count();
+        }
+        line
     }
-    fn peek_nth(&mut self, n: usize) -> PhysicalLine {
-        self.inner.peek_nth(n).map(|s| *s).into()
+    fn peek(&mut self) -> PhysicalLine<'a> {
+        self.inner.peek().copied().into()
+    }
+    fn peek_nth(&mut self, n: usize) -> PhysicalLine<'a> {
+        self.inner.peek_nth(n).copied().into()
     }
     fn advance_if_ok<T>(&mut self, x: Result<T, ParserError>) -> Result<T, ParserError> {
         if x.is_ok() {
@@ -79,41 +127,55 @@ impl<'a> LineScanner<'a> {
     fn advance(&mut self) {
         self.next();
     }
-    fn advance_by(&mut self, n: usize) {
-        for _ in 0..n {
-            self.advance();
-        }
-    }
 }
 
 /// Scanner commands for single (logical) lines
 impl<'a> LineScanner<'a> {
     pub fn peek_element_attrs(&mut self) -> Result<ElementAttrs, ParserError> {
-        self.peek()
-            .flat_map_line(|line| reader::ElementAttrs::parse(line).map(ElementAttrs::from))
+        self.peek().flat_map_line(|line| {
+            reader::ElementAttrs::parse(line.trim_end())
+                .map(|attrs| ElementAttrs::from(attrs).with_shorthand())
+        })
     }
     pub fn peek_delimiter(&mut self) -> Result<Delimiter, ParserError> {
         self.peek()
-            .flat_map_line(|line| Delimiter::parse(line).ok_or(ParserError::Mismatch))
+            .flat_map_line(|line| Delimiter::parse(line.trim_end()).ok_or(ParserError::Mismatch))
     }
     pub fn peek_non_empty_line(&mut self) -> Result<String, ParserError> {
         self.peek().map_line(str::to_owned)
     }
+    /// Try to parse the next line as `T`
+    pub fn peek_parse<T: Parser>(&mut self) -> Result<T, ParserError> {
+        self.peek_nth_parse(0)
+    }
+    /// Try to parse the `n`th line ahead as `T`
+    pub fn peek_nth_parse<T: Parser>(&mut self, n: usize) -> Result<T, ParserError> {
+        self.peek_nth(n).flat_map_line(T::parse)
+    }
     pub fn peek_empty(&mut self) -> bool {
-        let next = self.peek();
-        if matches!(next, PhysicalLine::Empty) {
-            true
-        } else {
-            false
-        }
+        matches!(self.peek(), PhysicalLine::Empty)
     }
     pub fn peek_eof(&mut self) -> bool {
-        let next = self.peek();
-        if matches!(next, PhysicalLine::EOF) {
-            true
-        } else {
-            false
-        }
+        matches!(self.peek(), PhysicalLine::Eof)
+    }
+    pub fn peek_nth_empty(&mut self, n: usize) -> bool {
+        matches!(self.peek_nth(n), PhysicalLine::Empty)
+    }
+    pub fn peek_nth_eof(&mut self, n: usize) -> bool {
+        matches!(self.peek_nth(n), PhysicalLine::Eof)
+    }
+    pub fn element_attrs(&mut self) -> Result<ElementAttrs, ParserError> {
+        let attrs = self.peek_element_attrs();
+        self.advance_if_ok(attrs)
+    }
+    pub fn non_empty_line(&mut self) -> Result<String, ParserError> {
+        let line = self.peek_non_empty_line();
+        self.advance_if_ok(line)
+    }
+    /// Parse the next line as `T`, advancing only on success
+    pub fn parse<T: Parser>(&mut self) -> Result<T, ParserError> {
+        let x = self.peek_parse();
+        self.advance_if_ok(x)
     }
 }
 
@@ -135,19 +197,76 @@ impl<'a> LineScanner<'a> {
             let line = self.peek();
             match line {
                 PhysicalLine::Line(line) => lines.push(line.to_owned()),
-                PhysicalLine::EOF | PhysicalLine::Empty => break,
+                PhysicalLine::Eof | PhysicalLine::Empty => break,
             }
             self.advance();
         }
         lines
     }
-    pub fn delimited_block(&mut self) -> Vec<String> {
-        todo!()
+    /// Scan the lines of a paragraph. A paragraph is interrupted by an empty line, a block
+    /// delimiter, a block attribute line or a block anchor.
+    pub fn paragraph_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let PhysicalLine::Line(line) = self.peek() {
+            if !lines.is_empty()
+                && (self.peek_delimiter().is_ok()
+                    || self.peek_element_attrs().is_ok()
+                    || self.peek_parse::<BlockAnchor>().is_ok())
+            {
+                break;
+            }
+            lines.push(line.to_owned());
+            self.advance();
+        }
+        lines
     }
-    /// A block where every line is prefixed by `prefix`. 
-    /// 
+    /// Scan the lines of a list item following its first line. These are also interrupted
+    /// by another list item or a list continuation.
+    pub fn list_item_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let PhysicalLine::Line(line) = self.peek() {
+            if self.peek_delimiter().is_ok()
+                || self.peek_element_attrs().is_ok()
+                || self.peek_parse::<BlockAnchor>().is_ok()
+                || self.peek_parse::<ListItemLine>().is_ok()
+                || self.peek_parse::<ListContinuation>().is_ok()
+            {
+                break;
+            }
+            lines.push(line.trim_start().to_owned());
+            self.advance();
+        }
+        lines
+    }
+    /// Scan a delimited block, starting from the opening delimiter. Returns the lines between
+    /// the delimiters as they are, so that verbatim content keeps lines of whitespace. An
+    /// unclosed block extends to EOF.
+    pub fn delimited_block(&mut self) -> Result<Vec<String>, ParserError> {
+        let delimiter = self.peek_delimiter()?;
+        let opening = self.peek_non_empty_line()?;
+        let opening = opening.trim_end();
+        self.advance();
+        let mut lines = Vec::new();
+        while let Some(line) = self.next_raw() {
+            if delimiter.is_closed_by(opening, line.trim_end()) {
+                break;
+            }
+            lines.push(line.to_owned());
+        }
+        Ok(lines)
+    }
+    /// A block where every line is prefixed by `prefix`. The prefix is stripped.
+    ///
     /// For instance, an indented block.
     pub fn prefixed_block(&mut self, prefix: &str) -> Vec<String> {
-        todo!()
+        let mut lines = Vec::new();
+        while let PhysicalLine::Line(line) = self.peek() {
+            match line.strip_prefix(prefix) {
+                Some(rest) => lines.push(rest.to_owned()),
+                None => break,
+            }
+            self.advance();
+        }
+        lines
     }
 }
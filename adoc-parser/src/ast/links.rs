@@ -0,0 +1,297 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    document::DocumentAttrs,
+    element::ElementAttrs,
+    inline::{Inline, InlineKind},
+    macros::{self, macro_attrs},
+    subs::{replace_all, Node, Stashed},
+};
+
+/// A link, `link:target[text]`, `mailto:address[text]` or an autolink. The text is the
+/// content of the inline; when empty, the target is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub target: String,
+    /// From the `window` attribute, or `_blank` for a `^` at the end of the text
+    pub window: Option<String>,
+    pub content: Vec<Inline>,
+}
+
+impl Link {
+    /// Text shown for a link without text. The `mailto:` scheme is always hidden, others
+    /// when `hide-uri-scheme` is set.
+    pub fn default_text<'a>(&'a self, attrs: &DocumentAttrs) -> &'a str {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^[a-z][a-z+.\-]*:(?://)?").unwrap();
+        }
+        match self.target.strip_prefix("mailto:") {
+            Some(address) => address,
+            None if attrs.is_set("hide-uri-scheme") => match RE.find(&self.target) {
+                Some(scheme) => &self.target[scheme.end()..],
+                None => &self.target,
+            },
+            None => &self.target,
+        }
+    }
+}
+
+const URL: &str = r"(?:https?|ftp|irc)://";
+
+/// Substitute links in text nodes. Macros come first, then links in angle brackets, then
+/// autolinks. Targets are taken as they are written, before replacements.
+pub fn substitute_links(mut nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_MACRO: Regex = Regex::new(&format!(
+            r"(\\)?(link:([^\s\[]+)|{}[^\s\[\]<>]+|mailto:([^\s\[]+))\[((?:\\\]|[^\]])*)\]",
+            URL
+        ))
+        .unwrap();
+        static ref RE_ANGLE: Regex = Regex::new(&format!(r"(\\)?<({}[^\s>]+)>", URL)).unwrap();
+        static ref RE_URL: Regex =
+            Regex::new(&format!(r#"(?m)(^|[\s(>"'\[])(\\)?({}[^\s\[\]<>"]+)"#, URL)).unwrap();
+        static ref RE_EMAIL: Regex = Regex::new(
+            r"(?m)(^|[\s(>:])(\\)?((?:mailto:)?[\w.%+\-]+@[\w\-]+(?:\.[\w\-]+)*\.[a-zA-Z]{2,})"
+        )
+        .unwrap();
+    }
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MACRO, |caps| link_macro(s, caps))
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_ANGLE, |caps| {
+            Some(vec![match caps.get(1) {
+                Some(_) => Node::Literal(caps[0][1..].to_owned()),
+                None => autolink(&s.source(&caps[2])),
+            }])
+        })
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_URL, |caps| bare_url(s, caps))
+    });
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_EMAIL, |caps| bare_url(s, caps))
+    })
+}
+
+/// `link:target[attrs]`, `url[attrs]` or `mailto:address[text, subject, body]`
+fn link_macro(s: &Stashed, caps: &Captures) -> Option<Vec<Node>> {
+    if caps.get(1).is_some() {
+        return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+    }
+    let text = macros::unescape(&caps[5]);
+    // Text is only split into attributes when it has named ones
+    let attrs = match text.contains('=') {
+        true => macro_attrs(&text),
+        false => ElementAttrs::default(),
+    };
+    let mut text = match text.contains('=') {
+        true => attrs.positional(0).unwrap_or("").to_owned(),
+        false => text,
+    };
+    let target = match (caps.get(3), caps.get(4)) {
+        (Some(target), _) => s.source(target.as_str()),
+        (_, Some(address)) => {
+            // Subject and body follow the text
+            let attrs = macro_attrs(&caps[5]);
+            text = attrs.positional(0).unwrap_or("").to_owned();
+            let (subject, body) = (attrs.positional(1), attrs.positional(2));
+            let query = |q: Option<&str>| q.map(|q| s.source(q));
+            mailto(
+                &s.source(address.as_str()),
+                query(subject).as_deref(),
+                query(body).as_deref(),
+            )
+        }
+        _ => s.source(&caps[2]),
+    };
+    let mut window = attrs.get("window").map(str::to_owned);
+    if let Some(t) = text.strip_suffix('^') {
+        text = t.to_owned();
+        window = Some("_blank".to_owned());
+    }
+    let content = match text.is_empty() {
+        true => Vec::new(),
+        false => vec![Node::Text(text)],
+    };
+    Some(vec![link(target, window, attrs, content)])
+}
+
+/// A bare URL or email address, after a character that is not part of it. Trailing
+/// punctuation is not part of the URL, nor a closing parenthesis after an opening one.
+fn bare_url(s: &Stashed, caps: &Captures) -> Option<Vec<Node>> {
+    let prefix = Node::Text(caps[1].to_owned());
+    if caps.get(2).is_some() {
+        return Some(vec![prefix, Node::Literal(caps[3].to_owned())]);
+    }
+    let mut url = &caps[3];
+    let mut trailing = "";
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        let trimmed = match &caps[1] == "(" {
+            true => trimmed.strip_suffix(')').unwrap_or(trimmed),
+            false => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            break;
+        }
+        trailing = &caps[3][trimmed.len()..];
+        url = trimmed;
+    }
+    let mut nodes = vec![prefix, autolink(&s.source(url))];
+    if !trailing.is_empty() {
+        nodes.push(Node::Text(trailing.to_owned()));
+    }
+    Some(nodes)
+}
+
+/// A link showing its target. Email addresses link to `mailto:`.
+fn autolink(target: &str) -> Node {
+    let target = match target.contains("://") || target.starts_with("mailto:") {
+        true => target.to_owned(),
+        false => format!("mailto:{}", target),
+    };
+    link(target, None, ElementAttrs::default(), Vec::new())
+}
+
+fn link(target: String, window: Option<String>, attrs: ElementAttrs, content: Vec<Node>) -> Node {
+    let inline = Inline {
+        attrs,
+        kind: InlineKind::Link(Link {
+            target,
+            window,
+            content: Vec::new(),
+        }),
+    };
+    Node::Inline(inline, content)
+}
+
+/// A `mailto:` target with an optional subject and body
+fn mailto(address: &str, subject: Option<&str>, body: Option<&str>) -> String {
+    let query: Vec<String> = [("subject", subject), ("body", body)]
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{}={}", name, encode(value?))))
+        .collect();
+    match query.is_empty() {
+        true => format!("mailto:{}", address),
+        false => format!("mailto:{}?{}", address, query.join("&")),
+    }
+}
+
+/// Percent-encode a query parameter
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{replacements::substitute_replacements, subs::apply_with};
+
+    fn link(inline: &Inline) -> &Link {
+        match &inline.kind {
+            InlineKind::Link(link) => link,
+            other => panic!("expected a link, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn autolinks() {
+        let l = apply_with(
+            "See https://example.org/a_b. Or (http://example.org/x), <irc://irc.libera.chat>",
+            substitute_links,
+        );
+        assert_eq!(l[0], Inline::unquoted("See "));
+        assert_eq!(link(&l[1]).target, "https://example.org/a_b");
+        assert!(link(&l[1]).content.is_empty());
+        assert_eq!(l[2], Inline::unquoted(". Or ("));
+        assert_eq!(link(&l[3]).target, "http://example.org/x");
+        assert_eq!(l[4], Inline::unquoted("), "));
+        assert_eq!(link(&l[5]).target, "irc://irc.libera.chat");
+        assert_eq!(l.len(), 6);
+    }
+
+    #[test]
+    fn email_autolinks() {
+        let l = apply_with(
+            "Write to doc@example.org, or mailto:team@example.org.",
+            substitute_links,
+        );
+        assert_eq!(link(&l[1]).target, "mailto:doc@example.org");
+        assert_eq!(link(&l[3]).target, "mailto:team@example.org");
+        assert_eq!(l[4], Inline::unquoted("."));
+    }
+
+    #[test]
+    fn link_macros() {
+        let l = apply_with(
+            "link:index.html[Docs] https://example.org[Example^] \
+             link:/r[Report, window=_self, role=ext]",
+            substitute_links,
+        );
+        assert_eq!(link(&l[0]).target, "index.html");
+        assert_eq!(link(&l[0]).content, vec![Inline::unquoted("Docs")]);
+        assert_eq!(link(&l[2]).target, "https://example.org");
+        assert_eq!(link(&l[2]).window.as_deref(), Some("_blank"));
+        assert_eq!(link(&l[2]).content, vec![Inline::unquoted("Example")]);
+        assert_eq!(link(&l[4]).window.as_deref(), Some("_self"));
+        assert_eq!(link(&l[4]).content, vec![Inline::unquoted("Report")]);
+        assert_eq!(l[4].attrs.roles(), vec!["ext"]);
+    }
+
+    #[test]
+    fn mailto_macro() {
+        let l = apply_with(
+            "mailto:join@example.org[Subscribe, Subscribe me, I want in]",
+            substitute_links,
+        );
+        assert_eq!(
+            link(&l[0]).target,
+            "mailto:join@example.org?subject=Subscribe%20me&body=I%20want%20in"
+        );
+        assert_eq!(link(&l[0]).content, vec![Inline::unquoted("Subscribe")]);
+    }
+
+    #[test]
+    fn escaped_links() {
+        assert_eq!(
+            apply_with("\\https://example.org and \\link:a[b]", substitute_links),
+            vec![Inline::unquoted("https://example.org and link:a[b]")]
+        );
+    }
+
+    #[test]
+    fn replacements_in_targets() {
+        let l = apply_with(
+            "See https://example.org/x...y and link:it's--here.html[it's] or \
+             mailto:a@example.org[Hi, it's]",
+            |nodes| substitute_links(substitute_replacements(nodes)),
+        );
+        assert_eq!(link(&l[1]).target, "https://example.org/x...y");
+        assert_eq!(link(&l[3]).target, "it's--here.html");
+        assert_eq!(Inline::text(&link(&l[3]).content), "it\u{2019}s");
+        assert_eq!(link(&l[5]).target, "mailto:a@example.org?subject=it%27s");
+        assert_eq!(l.len(), 6);
+    }
+
+    #[test]
+    fn hidden_uri_scheme() {
+        let mut attrs = DocumentAttrs::default();
+        let l = apply_with(
+            "https://example.org mailto:a@example.org[]",
+            substitute_links,
+        );
+        assert_eq!(link(&l[0]).default_text(&attrs), "https://example.org");
+        assert_eq!(link(&l[2]).default_text(&attrs), "a@example.org");
+        attrs.set("hide-uri-scheme", "");
+        assert_eq!(link(&l[0]).default_text(&attrs), "example.org");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::ast::reader::{ListContinuation, ListItemLine, ListMarker, ParserError};
+
+use super::{
+    block::{Block, BlockContext, BlockMetadata, CompoundBlock, SectionScope, SimpleBlock},
+    line_scanner::LineScanner,
+    span::Span,
+    state::ParserState,
+    subs::Substitutions,
+};
+
+/// List parsing
+impl Block {
+    /// Parse a list starting at a list item line. `ancestors` are the markers of the
+    /// enclosing lists; an item with one of those markers ends this list.
+    pub fn list(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        meta: BlockMetadata,
+        ancestors: &[ListMarker],
+    ) -> Result<Block, ParserError> {
+        let start = scanner.position();
+        let marker = scanner.peek_parse::<ListItemLine>()?.marker;
+        let mut nested_ancestors = ancestors.to_vec();
+        nested_ancestors.push(marker.clone());
+        let mut items = Vec::new();
+        while let Ok(line) = scanner.peek_parse::<ListItemLine>() {
+            if line.marker != marker {
+                break;
+            }
+            items.push(Self::list_item(scanner, state, &nested_ancestors)?);
+            // Items may be separated by empty lines
+            let mut n = 0;
+            while scanner.peek_nth_empty(n) {
+                n += 1;
+            }
+            match scanner.peek_nth_parse::<ListItemLine>(n) {
+                Ok(next) if next.marker == marker => {
+                    scanner.empty_lines();
+                }
+                _ => break,
+            }
+        }
+        let context = match marker {
+            ListMarker::Unordered(_) => BlockContext::UnorderedList(CompoundBlock(items)),
+            ListMarker::Ordered(_) => BlockContext::OrderedList(CompoundBlock(items)),
+        };
+        let span = Span {
+            start,
+            end: scanner.end_position(),
+        };
+        Ok(meta.into_block(context, span))
+    }
+
+    /// A list item is its text, followed by attached blocks and nested lists
+    fn list_item(
+        scanner: &mut LineScanner,
+        state: &mut ParserState,
+        ancestors: &[ListMarker],
+    ) -> Result<Block, ParserError> {
+        let start = scanner.position();
+        let first = scanner.parse::<ListItemLine>()?;
+        let mut lines = vec![first.text];
+        lines.extend(scanner.list_item_lines());
+        let text = Block::new(
+            BlockContext::Paragraph(SimpleBlock::new(&lines, &Substitutions::normal(), state)),
+            Span {
+                start,
+                end: scanner.end_position(),
+            },
+        );
+        let mut blocks = vec![text];
+        loop {
+            if scanner.parse::<ListContinuation>().is_ok() {
+                let meta = BlockMetadata::parse(scanner, state);
+                blocks.extend(Self::parse_one(scanner, state, SectionScope::None, meta)?);
+                continue;
+            }
+            let mut n = 0;
+            while scanner.peek_nth_empty(n) {
+                n += 1;
+            }
+            match scanner.peek_nth_parse::<ListItemLine>(n) {
+                // A new marker starts a nested list
+                Ok(next) if !ancestors.contains(&next.marker) => {
+                    scanner.empty_lines();
+                    let meta = BlockMetadata::default();
+                    blocks.push(Self::list(scanner, state, meta, ancestors)?);
+                }
+                _ => break,
+            }
+        }
+        let span = Span {
+            start,
+            end: scanner.end_position(),
+        };
+        Ok(Block::new(
+            BlockContext::ListItem(CompoundBlock(blocks)),
+            span,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::document::Document;
+
+    /// Contexts of the blocks in a list, with nested lists in brackets
+    fn shape(block: &Block) -> String {
+        let children = |c: &CompoundBlock| c.0.iter().map(shape).collect::<Vec<_>>().join(" ");
+        match &block.context {
+            BlockContext::UnorderedList(items) => format!("ul[{}]", children(items)),
+            BlockContext::OrderedList(items) => format!("ol[{}]", children(items)),
+            BlockContext::ListItem(blocks) => format!("li({})", children(blocks)),
+            BlockContext::Paragraph(_) => "p".to_owned(),
+            BlockContext::Listing(_) => "listing".to_owned(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    fn shapes(s: &str) -> Vec<String> {
+        Document::parse(s)
+            .unwrap()
+            .blocks
+            .iter()
+            .map(shape)
+            .collect()
+    }
+
+    #[test]
+    fn flat_lists() {
+        assert_eq!(shapes("* a\n* b\n\n* c"), vec!["ul[li(p) li(p) li(p)]"]);
+        assert_eq!(shapes(". a\n. b"), vec!["ol[li(p) li(p)]"]);
+        assert_eq!(shapes("* a\n\n\nparagraph"), vec!["ul[li(p)]", "p"]);
+    }
+
+    #[test]
+    fn nested_lists() {
+        assert_eq!(
+            shapes("* a\n** b\n** c\n* d\n. e"),
+            vec!["ul[li(p ul[li(p) li(p)]) li(p ol[li(p)])]"]
+        );
+        assert_eq!(shapes("- a\n* b\n- c"), vec!["ul[li(p ul[li(p)]) li(p)]"]);
+    }
+
+    #[test]
+    fn list_continuation() {
+        assert_eq!(
+            shapes("* a\ncontinued\n+\n----\ncode\n----\n* b"),
+            vec!["ul[li(p listing) li(p)]"]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use super::{
+    anchors::substitute_anchors, element::ElementAttrs, extensions::substitute_custom_macros,
+    footnotes::substitute_footnotes, index::substitute_index_terms, links::substitute_links,
+    media::substitute_images, reader, reader::Parser, state::ParserState, subs::Node,
+    ui::substitute_ui_macros, xref::substitute_xrefs,
+};
+
+/// Substitute inline macros in text nodes. UI macros are only substituted when the
+/// `experimental` attribute is set. Custom macros are substituted last.
+pub fn substitute_macros(mut nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+    if state.attrs.is_set("experimental") {
+        nodes = substitute_ui_macros(nodes);
+    }
+    let nodes = substitute_index_terms(substitute_images(nodes, state));
+    let nodes = substitute_anchors(nodes);
+    let nodes = substitute_links(substitute_xrefs(nodes));
+    // After the others, so that macros in the text of footnotes are already substituted
+    let nodes = substitute_footnotes(nodes);
+    substitute_custom_macros(nodes, state)
+}
+
+/// Attributes in the brackets of a macro. Text which is not an attribute list is the first
+/// positional attribute.
+pub fn macro_attrs(text: &str) -> ElementAttrs {
+    match reader::ElementAttrs::parse(&format!("[{}]", text)) {
+        Ok(attrs) => attrs.into(),
+        Err(_) => {
+            let attr = reader::ElementAttr::Positional(unescape(text));
+            reader::ElementAttrs(vec![attr]).into()
+        }
+    }
+}
+
+/// Text in the brackets of a macro, where `\]` is an escaped bracket
+pub fn unescape(text: &str) -> String {
+    text.replace("\\]", "]")
+}
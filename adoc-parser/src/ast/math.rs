@@ -0,0 +1,123 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    document::DocumentAttrs,
+    inline::{Inline, InlineKind},
+    macros,
+    state::ParserState,
+    subs::{replace_all, Node},
+};
+
+/// Notation of STEM content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathNotation {
+    AsciiMath,
+    LatexMath,
+}
+
+impl MathNotation {
+    /// Notation of a block style or macro name, `asciimath`, `latexmath`, or `stem` for the
+    /// document's default in the `stem` attribute, AsciiMath unless it says otherwise
+    pub fn from_name(name: &str, attrs: &DocumentAttrs) -> Option<Self> {
+        match name {
+            "asciimath" => Some(Self::AsciiMath),
+            "latexmath" => Some(Self::LatexMath),
+            "stem" => match attrs.get("stem") {
+                Some("latexmath" | "latex" | "tex") => Some(Self::LatexMath),
+                _ => Some(Self::AsciiMath),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A formula, from a `[stem]` passthrough block or a `stem:[source]` macro. How it is
+/// rendered is up to the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Math {
+    pub notation: MathNotation,
+    pub source: String,
+    /// Displayed as a block, rather than inline
+    pub display: bool,
+}
+
+/// Substitute inline STEM macros, `stem:[]`, `asciimath:[]` and `latexmath:[]`, in text
+/// nodes. Like passthroughs, their source is not open to other substitutions.
+pub fn substitute_stem(nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"(\\)?(stem|asciimath|latexmath):\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE, |caps| {
+            if caps.get(1).is_some() {
+                return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+            }
+            let inline = Inline::new(InlineKind::Math(Math {
+                notation: MathNotation::from_name(&caps[2], &state.attrs)?,
+                source: macros::unescape(caps[3].trim()),
+                display: false,
+            }));
+            Some(vec![Node::Inline(inline, Vec::new())])
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{block::BlockContext, document::Document, subs::Substitutions};
+
+    fn math(inline: &Inline) -> &Math {
+        match &inline.kind {
+            InlineKind::Math(math) => math,
+            other => panic!("expected math, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn inline_stem() {
+        let mut state = ParserState::default();
+        state.attrs.set("stem", "latexmath");
+        let inlines = Substitutions::normal().apply(
+            "stem:[a*b*c] and asciimath:[[a,b\\]] not \\stem:[x]",
+            &state,
+        );
+        assert_eq!(
+            math(&inlines[0]),
+            &Math {
+                notation: MathNotation::LatexMath,
+                source: "a*b*c".to_owned(),
+                display: false
+            }
+        );
+        assert_eq!(math(&inlines[2]).notation, MathNotation::AsciiMath);
+        assert_eq!(math(&inlines[2]).source, "[a,b]");
+        assert_eq!(inlines[3], Inline::unquoted(" not stem:[x]"));
+    }
+
+    #[test]
+    fn stem_blocks() {
+        let doc = Document::parse(
+            ":stem:\n\n[stem]\n++++\nsqrt(4) = 2\n++++\n\n[latexmath]\n++++\n\\sqrt{4}\n++++",
+        )
+        .unwrap();
+        let blocks: Vec<_> = doc.blocks.iter().map(|b| &b.context).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                &BlockContext::Stem(Math {
+                    notation: MathNotation::AsciiMath,
+                    source: "sqrt(4) = 2".to_owned(),
+                    display: true
+                }),
+                &BlockContext::Stem(Math {
+                    notation: MathNotation::LatexMath,
+                    source: "\\sqrt{4}".to_owned(),
+                    display: true
+                }),
+            ]
+        );
+    }
+}
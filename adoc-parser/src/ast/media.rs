@@ -0,0 +1,328 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    element::ElementAttrs,
+    inline::{Inline, InlineKind},
+    macros::macro_attrs,
+    state::ParserState,
+    subs::{replace_all, Node, Stashed},
+};
+
+/// An image block, `image::target[alt, width, height]`, or an inline image,
+/// `image:target[alt, width, height]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBlock {
+    /// The target as written
+    pub target: String,
+    /// The target resolved against `imagesdir`
+    pub src: String,
+    /// From the first positional attribute or `alt`, or derived from the target
+    pub alt: String,
+    pub width: Option<String>,
+    pub height: Option<String>,
+    pub link: Option<String>,
+    pub align: Option<String>,
+    pub float: Option<String>,
+}
+
+/// A video block, `video::target[poster, width, height]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoBlock {
+    pub target: String,
+    /// The target resolved against `imagesdir`. Targets of hosted videos (YouTube, Vimeo)
+    /// are IDs and not resolved.
+    pub src: String,
+    pub poster: Option<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+    /// Start and end times, in seconds
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub autoplay: bool,
+    pub loop_: bool,
+    pub muted: bool,
+    pub controls: bool,
+}
+
+/// An audio block, `audio::target[]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioBlock {
+    pub target: String,
+    /// The target resolved against `imagesdir`
+    pub src: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub autoplay: bool,
+    pub loop_: bool,
+    pub controls: bool,
+}
+
+impl ImageBlock {
+    pub fn new(target: &str, attrs: &ElementAttrs, state: &ParserState) -> Self {
+        let alt = attrs
+            .get("alt")
+            .or_else(|| attrs.positional(0))
+            .filter(|a| !a.is_empty())
+            .map(str::to_owned)
+            .unwrap_or_else(|| default_alt(target));
+        Self {
+            target: target.to_owned(),
+            src: resolve(target, state),
+            alt,
+            width: attr(attrs, "width", 1),
+            height: attr(attrs, "height", 2),
+            link: named(attrs, "link"),
+            align: named(attrs, "align"),
+            float: named(attrs, "float"),
+        }
+    }
+}
+
+impl VideoBlock {
+    pub fn new(target: &str, attrs: &ElementAttrs, state: &ParserState) -> Self {
+        // The first positional attribute is the poster, or the hosting service
+        let hosted = matches!(attrs.positional(0), Some("youtube" | "vimeo"));
+        let (src, poster) = match hosted {
+            true => (target.to_owned(), named(attrs, "poster")),
+            false => (resolve(target, state), attr(attrs, "poster", 0)),
+        };
+        Self {
+            target: target.to_owned(),
+            src,
+            poster,
+            width: attr(attrs, "width", 1),
+            height: attr(attrs, "height", 2),
+            start: named(attrs, "start"),
+            end: named(attrs, "end"),
+            autoplay: attrs.has_option("autoplay"),
+            loop_: attrs.has_option("loop"),
+            muted: attrs.has_option("muted"),
+            controls: !attrs.has_option("nocontrols"),
+        }
+    }
+}
+
+impl AudioBlock {
+    pub fn new(target: &str, attrs: &ElementAttrs, state: &ParserState) -> Self {
+        Self {
+            target: target.to_owned(),
+            src: resolve(target, state),
+            start: named(attrs, "start"),
+            end: named(attrs, "end"),
+            autoplay: attrs.has_option("autoplay"),
+            loop_: attrs.has_option("loop"),
+            controls: !attrs.has_option("nocontrols"),
+        }
+    }
+}
+
+/// An icon, `icon:name[size]`. Other attributes, such as `role`, `title`, `flip` and `rotate`,
+/// are attributes of the inline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icon {
+    pub name: String,
+    /// From the first positional attribute or `size`, such as `2x`
+    pub size: Option<String>,
+}
+
+/// Substitute inline images, `image:target[alt, width, height]`, and icons in text nodes.
+/// Inline images have the attributes of image blocks. Targets are taken as they are
+/// written, and attributes as plain text.
+pub fn substitute_images(mut nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_IMAGE: Regex =
+            Regex::new(r"(\\)?image:([^:\s\[](?:[^\n\[]*[^\s\[])?)\[((?:\\\]|[^\]])*)\]").unwrap();
+        static ref RE_ICON: Regex =
+            Regex::new(r"(\\)?icon:([^\s\[]+)\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    let inline_macro = |s: &Stashed, caps: &Captures, f: &dyn Fn(&ElementAttrs) -> InlineKind| {
+        if caps.get(1).is_some() {
+            return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+        }
+        let attrs = macro_attrs(&s.text(&caps[3]));
+        let kind = f(&attrs);
+        Some(vec![Node::Inline(Inline { attrs, kind }, Vec::new())])
+    };
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_IMAGE, |caps| {
+            inline_macro(s, caps, &|attrs| {
+                let target = s.source(&caps[2]);
+                InlineKind::Image(Box::new(ImageBlock::new(&target, attrs, state)))
+            })
+        })
+    });
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_ICON, |caps| {
+            inline_macro(s, caps, &|attrs| {
+                InlineKind::Icon(Icon {
+                    name: s.source(&caps[2]),
+                    size: attr(attrs, "size", 0),
+                })
+            })
+        })
+    })
+}
+
+/// A named attribute. Empty values are unset.
+fn named(attrs: &ElementAttrs, name: &str) -> Option<String> {
+    attrs.get(name).filter(|v| !v.is_empty()).map(str::to_owned)
+}
+
+/// A named attribute, or else the positional attribute at `index`
+fn attr(attrs: &ElementAttrs, name: &str, index: usize) -> Option<String> {
+    named(attrs, name).or_else(|| {
+        attrs
+            .positional(index)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+    })
+}
+
+/// Resolve a relative target against the `imagesdir` attribute. URLs, data URIs and
+/// absolute paths are kept.
+fn resolve(target: &str, state: &ParserState) -> String {
+    let dir = state.attrs.get("imagesdir").unwrap_or("");
+    if dir.is_empty()
+        || target.contains("://")
+        || target.starts_with("data:")
+        || target.starts_with(['/', '\\'])
+    {
+        return target.to_owned();
+    }
+    format!("{}/{}", dir.trim_end_matches('/'), target)
+}
+
+/// Alt text from the file name of the target, without extension
+fn default_alt(target: &str) -> String {
+    let name = target.rsplit('/').next().unwrap_or(target);
+    let name = name.rsplit_once('.').map(|(n, _)| n).unwrap_or(name);
+    name.replace(['-', '_'], " ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{block::BlockContext, document::Document};
+
+    fn contexts(s: &str) -> Vec<BlockContext> {
+        let doc = Document::parse(s).unwrap();
+        doc.blocks.into_iter().map(|b| b.context).collect()
+    }
+
+    #[test]
+    fn default_alt_text() {
+        assert_eq!(default_alt("images/sunset-beach_2.jpg"), "sunset beach 2");
+        assert_eq!(default_alt("logo"), "logo");
+    }
+
+    #[test]
+    fn image_blocks() {
+        let c = contexts(
+            ":imagesdir: images/\n\nimage::sunset.jpg[Sunset, 300, 200, link=https://example.org]\n\n\
+             [.right, float=right, align=center]\nimage::https://example.org/tiger-cub.png[]",
+        );
+        match &c[0] {
+            BlockContext::Image(image) => {
+                assert_eq!(image.src, "images/sunset.jpg");
+                assert_eq!(image.alt, "Sunset");
+                assert_eq!(image.width.as_deref(), Some("300"));
+                assert_eq!(image.height.as_deref(), Some("200"));
+                assert_eq!(image.link.as_deref(), Some("https://example.org"));
+            }
+            other => panic!("expected an image, got {:?}", other),
+        }
+        match &c[1] {
+            BlockContext::Image(image) => {
+                assert_eq!(image.src, "https://example.org/tiger-cub.png");
+                assert_eq!(image.alt, "tiger cub");
+                assert_eq!(image.width, None);
+                assert_eq!(image.float.as_deref(), Some("right"));
+                assert_eq!(image.align.as_deref(), Some("center"));
+            }
+            other => panic!("expected an image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn image_title() {
+        let doc =
+            Document::parse("image::a.png[title=\"A title\"]\n\n.Other\nimage::b.png[]").unwrap();
+        assert!(doc.blocks[0].title.is_some());
+        assert!(doc.blocks[1].title.is_some());
+    }
+
+    #[test]
+    fn video_and_audio_blocks() {
+        let c = contexts(
+            ":imagesdir: media\n\nvideo::intro.mp4[poster.png, 640, 480, start=10, end=20, opts=\"autoplay,loop\"]\n\n\
+             video::rPQoq7ThGAU[youtube]\n\n[%nocontrols]\naudio::ocean.wav[start=5]",
+        );
+        match &c[0] {
+            BlockContext::Video(video) => {
+                assert_eq!(video.src, "media/intro.mp4");
+                assert_eq!(video.poster.as_deref(), Some("poster.png"));
+                assert_eq!(video.width.as_deref(), Some("640"));
+                assert_eq!(video.start.as_deref(), Some("10"));
+                assert_eq!(video.end.as_deref(), Some("20"));
+                assert!(video.autoplay && video.loop_ && video.controls);
+            }
+            other => panic!("expected a video, got {:?}", other),
+        }
+        match &c[1] {
+            BlockContext::Video(video) => assert_eq!(video.src, "rPQoq7ThGAU"),
+            other => panic!("expected a video, got {:?}", other),
+        }
+        match &c[2] {
+            BlockContext::Audio(audio) => {
+                assert_eq!(audio.src, "media/ocean.wav");
+                assert_eq!(audio.start.as_deref(), Some("5"));
+                assert!(!audio.controls);
+            }
+            other => panic!("expected audio, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn inline_images_and_icons() {
+        let doc = Document::parse(
+            ":imagesdir: img\n\nA image:heart.png[Heart, 16] and icon:heart[2x, role=red].",
+        )
+        .unwrap();
+        let inlines = doc.inline().unwrap();
+        match &inlines[1].kind {
+            InlineKind::Image(image) => {
+                assert_eq!(image.src, "img/heart.png");
+                assert_eq!(image.alt, "Heart");
+                assert_eq!(image.width.as_deref(), Some("16"));
+            }
+            other => panic!("expected an image, got {:?}", other),
+        }
+        assert_eq!(
+            inlines[3].kind,
+            InlineKind::Icon(Icon {
+                name: "heart".to_owned(),
+                size: Some("2x".to_owned())
+            })
+        );
+        assert_eq!(inlines[3].attrs.roles(), vec!["red"]);
+    }
+
+    #[test]
+    fn replacements_in_targets() {
+        let doc = Document::parse("image:my--logo.png[It's] icon:it's[]").unwrap();
+        let inlines = doc.inline().unwrap();
+        match &inlines[0].kind {
+            InlineKind::Image(image) => {
+                assert_eq!(image.target, "my--logo.png");
+                assert_eq!(image.src, "my--logo.png");
+                assert_eq!(image.alt, "It\u{2019}s");
+            }
+            other => panic!("expected an image, got {:?}", other),
+        }
+        match &inlines[2].kind {
+            InlineKind::Icon(icon) => assert_eq!(icon.name, "it's"),
+            other => panic!("expected an icon, got {:?}", other),
+        }
+    }
+}
@@ -1,6 +1,30 @@
+pub mod anchors;
 pub mod block;
+pub mod breaks;
+pub mod callouts;
+pub mod catalog;
+pub mod diagnostic;
 pub mod document;
 pub mod element;
+pub mod extensions;
+pub mod footnotes;
+pub mod index;
 pub mod inline;
 pub mod line_scanner;
+pub mod links;
+pub mod list;
+pub mod macros;
+pub mod math;
+pub mod media;
+pub mod passthroughs;
+pub mod quotes;
 pub mod reader;
+pub mod replacements;
+pub mod section;
+pub mod source;
+pub mod span;
+pub mod state;
+pub mod subs;
+pub mod table;
+pub mod ui;
+pub mod xref;
@@ -0,0 +1,236 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    inline::{Inline, InlineKind},
+    macros,
+    math::substitute_stem,
+    state::ParserState,
+    subs::{replace_all, Node, Substitution, Substitutions},
+};
+
+/// An inline passthrough
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passthrough {
+    /// The substitutions the content got. Without special characters, the content is not
+    /// escaped.
+    pub subs: Substitutions,
+    pub content: Vec<Inline>,
+}
+
+/// Extract inline passthroughs from text nodes, before other substitutions. Text in
+/// `+text+` and `++text++` is only escaped, text in `+++text+++` and `pass:[text]` is
+/// raw, and `pass:subs[text]` gets the substitutions it names, such as `pass:q,a[]`.
+/// Extracted text is no longer text, so later substitutions skip it. Inline STEM macros
+/// are extracted too.
+pub fn extract_passthroughs(nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_TRIPLE: Regex = Regex::new(r"(\\)?\+\+\+((?s).*?)\+\+\+").unwrap();
+        static ref RE_MACRO: Regex =
+            Regex::new(r"(\\)?pass:([a-z_]+(?:,[a-z_]+)*)?\[((?:\\\]|[^\]])*)\]").unwrap();
+        static ref RE_DOUBLE: Regex = Regex::new(r"(\\)?\+\+((?s).+?)\+\+").unwrap();
+    }
+    let mut nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_TRIPLE, |caps| {
+            Some(match caps.get(1) {
+                Some(_) => escaped("+++", &caps[2]),
+                None => vec![raw(vec![Node::Literal(caps[2].to_owned())])],
+            })
+        })
+    });
+    nodes = substitute_stem(nodes, state);
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MACRO, |caps| {
+            if caps.get(1).is_some() {
+                return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+            }
+            let subs = match caps.get(2) {
+                Some(spec) => Substitutions::parse(spec.as_str(), &Substitutions::none()),
+                None => Substitutions::none(),
+            };
+            let text = macros::unescape(&caps[3]);
+            let content = seal(subs.substitute(vec![Node::Text(text)], state));
+            Some(vec![passthrough(subs, content)])
+        })
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_DOUBLE, |caps| {
+            Some(match caps.get(1) {
+                Some(_) => escaped("++", &caps[2]),
+                None => vec![escaped_only(&caps[2])],
+            })
+        })
+    });
+    Node::substitute(nodes, &|s| constrained(s))
+}
+
+/// A passthrough of `content`, whose special characters are not escaped
+pub fn raw(content: Vec<Node>) -> Node {
+    passthrough(Substitutions::none(), content)
+}
+
+/// A passthrough of text whose special characters are escaped, as in `+text+`
+fn escaped_only(text: &str) -> Node {
+    let subs = Substitutions(vec![Substitution::SpecialCharacters]);
+    passthrough(subs, vec![Node::Literal(text.to_owned())])
+}
+
+fn passthrough(subs: Substitutions, content: Vec<Node>) -> Node {
+    let inline = Inline::new(InlineKind::Passthrough(Passthrough {
+        subs,
+        content: Vec::new(),
+    }));
+    Node::Inline(inline, content)
+}
+
+/// Turn text into literals, so that the substitutions of the block don't apply to it
+fn seal(nodes: Vec<Node>) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .map(|node| match node.unmarked() {
+            Node::Text(s) => Node::Literal(s),
+            Node::Inline(inline, content) => Node::Inline(inline, seal(content)),
+            literal => literal,
+        })
+        .collect()
+}
+
+/// `+text+`, which is constrained like quoted text: not preceded or followed by a word
+/// character, and not starting or ending with a space
+fn constrained(s: &str) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(?m)(^|[^\w;:}])\+(\S|\S.*?\S)\+").unwrap();
+    }
+    let mut nodes = Vec::new();
+    let mut rest = 0;
+    let mut from = 0;
+    while let Some(caps) = RE.captures_at(s, from) {
+        let (prefix, end) = (caps.get(1).unwrap(), caps.get(0).unwrap().end());
+        let followed = s[end..].chars().next().map(is_word).unwrap_or(false);
+        if followed {
+            from = prefix.end() + 1;
+            continue;
+        }
+        let replaced = match prefix.as_str() {
+            "\\" => escaped("+", &caps[2]),
+            _ => vec![escaped_only(&caps[2])],
+        };
+        let start = match prefix.as_str() {
+            "\\" => prefix.start(),
+            _ => prefix.end(),
+        };
+        if start > rest {
+            nodes.push(Node::Text(s[rest..start].to_owned()));
+        }
+        nodes.extend(replaced);
+        rest = end;
+        from = end;
+    }
+    if rest < s.len() {
+        nodes.push(Node::Text(s[rest..].to_owned()));
+    }
+    nodes
+}
+
+/// An escaped passthrough, whose text is open to other substitutions
+fn escaped(mark: &str, text: &str) -> Vec<Node> {
+    vec![
+        Node::Literal(mark.to_owned()),
+        Node::Text(text.to_owned()),
+        Node::Literal(mark.to_owned()),
+    ]
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::inline::QuoteType;
+
+    fn parse(s: &str) -> Vec<Inline> {
+        let mut state = ParserState::default();
+        state.attrs.set("v", "1.0");
+        Substitutions::normal().apply(s, &state)
+    }
+
+    fn pass(subs: Vec<Substitution>, content: Vec<Inline>) -> Inline {
+        Inline::new(InlineKind::Passthrough(Passthrough {
+            subs: Substitutions(subs),
+            content,
+        }))
+    }
+
+    #[test]
+    fn literal_passthroughs() {
+        let escaped = |s| {
+            pass(
+                vec![Substitution::SpecialCharacters],
+                vec![Inline::unquoted(s)],
+            )
+        };
+        assert_eq!(
+            parse("Call +{v}*x*+ or ++a_b_c++ids, not a+b+c"),
+            vec![
+                Inline::unquoted("Call "),
+                escaped("{v}*x*"),
+                Inline::unquoted(" or "),
+                escaped("a_b_c"),
+                Inline::unquoted("ids, not a+b+c"),
+            ]
+        );
+        assert_eq!(
+            parse("\\+*b*+"),
+            vec![
+                Inline::unquoted("+"),
+                Inline::new(InlineKind::Quoted {
+                    ty: QuoteType::Strong,
+                    content: vec![Inline::unquoted("b")]
+                }),
+                Inline::unquoted("+")
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_passthroughs() {
+        assert_eq!(
+            parse("+++<u>{v}</u>+++ and pass:[<b>*x*</b>]"),
+            vec![
+                pass(vec![], vec![Inline::unquoted("<u>{v}</u>")]),
+                Inline::unquoted(" and "),
+                pass(vec![], vec![Inline::unquoted("<b>*x*</b>")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn pass_macro_subs() {
+        let strong = |s: &str| {
+            Inline::new(InlineKind::Quoted {
+                ty: QuoteType::Strong,
+                content: vec![Inline::unquoted(s)],
+            })
+        };
+        assert_eq!(
+            parse("pass:q[<u>*{v}*</u>]"),
+            vec![pass(
+                vec![Substitution::Quotes],
+                vec![
+                    Inline::unquoted("<u>"),
+                    strong("{v}"),
+                    Inline::unquoted("</u>")
+                ]
+            )]
+        );
+        assert_eq!(
+            parse("pass:c,a[<{v}>]"),
+            vec![pass(
+                vec![Substitution::SpecialCharacters, Substitution::Attributes],
+                vec![Inline::unquoted("<1.0>")]
+            )]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    element::ElementAttrs,
+    inline::{Inline, InlineKind, QuoteType},
+    reader,
+    subs::Node,
+};
+
+/// A form of a quote. Unconstrained quotes may appear anywhere. Constrained quotes must
+/// not be preceded or followed by a word character, and their text must not start or end
+/// with a space.
+struct QuoteForm {
+    ty: QuoteType,
+    /// The closing mark
+    close: &'static str,
+    constrained: bool,
+}
+
+const fn form(ty: QuoteType, close: &'static str, constrained: bool) -> QuoteForm {
+    QuoteForm {
+        ty,
+        close,
+        constrained,
+    }
+}
+
+/// Quote forms in the order they are substituted
+const FORMS: &[QuoteForm] = &[
+    form(QuoteType::Strong, "**", false),
+    form(QuoteType::Strong, "*", true),
+    form(QuoteType::Double, "`\"", true),
+    form(QuoteType::Single, "`'", true),
+    form(QuoteType::Monospaced, "``", false),
+    form(QuoteType::Monospaced, "`", true),
+    form(QuoteType::Emphasis, "__", false),
+    form(QuoteType::Emphasis, "_", true),
+    form(QuoteType::Mark, "##", false),
+    form(QuoteType::Mark, "#", true),
+    form(QuoteType::Superscript, "^", false),
+    form(QuoteType::Subscript, "~", false),
+];
+
+/// Substitute quoted text in text nodes
+pub fn substitute_quotes(mut nodes: Vec<Node>) -> Vec<Node> {
+    for form in FORMS {
+        nodes = Node::substitute(nodes, &|s| form.apply(s));
+    }
+    nodes
+}
+
+/// A match of a quote form. Ranges are byte offsets.
+struct QuoteMatch<'a> {
+    start: usize,
+    end: usize,
+    /// Number of backslashes escaping the quote
+    escape: usize,
+    attrs: Option<&'a str>,
+    text: &'a str,
+}
+
+impl QuoteForm {
+    fn apply(&self, s: &str) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut rest = 0;
+        let mut from = 0;
+        while let Some(m) = self.find(s, from) {
+            if m.start > rest {
+                nodes.push(Node::Text(s[rest..m.start].to_owned()));
+            }
+            let matched = &s[m.start..m.end];
+            let node = match m.escape {
+                0 => {
+                    let inline = Inline {
+                        attrs: m.attrs.map(quote_attrs).unwrap_or_default(),
+                        kind: InlineKind::Quoted {
+                            ty: self.ty,
+                            content: Vec::new(),
+                        },
+                    };
+                    Node::Inline(inline, vec![Node::Text(m.text.to_owned())])
+                }
+                // The backslashes are dropped, the rest is kept as is
+                n => Node::Literal(matched[n..].to_owned()),
+            };
+            nodes.push(Node::Markup(matched.to_owned(), Box::new(node)));
+            rest = m.end;
+            from = m.end;
+        }
+        if rest < s.len() {
+            nodes.push(Node::Text(s[rest..].to_owned()));
+        }
+        nodes
+    }
+
+    /// The leftmost match starting at or after `from`
+    fn find<'a>(&self, s: &'a str, from: usize) -> Option<QuoteMatch<'a>> {
+        match self.constrained {
+            true => self.find_constrained(s, from),
+            false => self.find_unconstrained(s, from),
+        }
+    }
+
+    fn find_unconstrained<'a>(&self, s: &'a str, from: usize) -> Option<QuoteMatch<'a>> {
+        lazy_static! {
+            static ref RE_STRONG: Regex = unconstrained(r"\*\*", r"((?s).+?)");
+            static ref RE_MONO: Regex = unconstrained(r"``", r"((?s).+?)");
+            static ref RE_EMPHASIS: Regex = unconstrained(r"__", r"((?s).+?)");
+            static ref RE_MARK: Regex = unconstrained(r"##", r"((?s).+?)");
+            static ref RE_SUPER: Regex = unconstrained(r"\^", r"(\S+?)");
+            static ref RE_SUB: Regex = unconstrained(r"~", r"(\S+?)");
+        }
+        let re: &Regex = match self.ty {
+            QuoteType::Strong => &RE_STRONG,
+            QuoteType::Monospaced => &RE_MONO,
+            QuoteType::Emphasis => &RE_EMPHASIS,
+            QuoteType::Mark => &RE_MARK,
+            QuoteType::Superscript => &RE_SUPER,
+            QuoteType::Subscript => &RE_SUB,
+            QuoteType::Double | QuoteType::Single => unreachable!(),
+        };
+        let caps = re.captures_at(s, from)?;
+        let whole = caps.get(0).unwrap();
+        Some(QuoteMatch {
+            start: whole.start(),
+            end: whole.end(),
+            escape: caps.get(1).map(|e| e.len()).unwrap_or(0),
+            attrs: caps.get(2).map(|a| a.as_str()),
+            text: caps.get(3).unwrap().as_str(),
+        })
+    }
+
+    /// Constrained quotes need a look-ahead, so the closing mark is searched by hand
+    fn find_constrained<'a>(&self, s: &'a str, from: usize) -> Option<QuoteMatch<'a>> {
+        lazy_static! {
+            static ref RE_STRONG: Regex = constrained(r"\*", "");
+            static ref RE_DOUBLE: Regex = constrained("\"`", "");
+            static ref RE_SINGLE: Regex = constrained("'`", "`");
+            static ref RE_MONO: Regex = constrained("`", "\"'`");
+            static ref RE_EMPHASIS: Regex = constrained("_", "");
+            static ref RE_MARK: Regex = constrained("#", "&");
+        }
+        let re: &Regex = match self.ty {
+            QuoteType::Strong => &RE_STRONG,
+            QuoteType::Double => &RE_DOUBLE,
+            QuoteType::Single => &RE_SINGLE,
+            QuoteType::Monospaced => &RE_MONO,
+            QuoteType::Emphasis => &RE_EMPHASIS,
+            QuoteType::Mark => &RE_MARK,
+            QuoteType::Superscript | QuoteType::Subscript => unreachable!(),
+        };
+        let mut from = from;
+        while let Some(caps) = re.captures_at(s, from) {
+            if let Some(m) = self.close_constrained(s, &caps) {
+                return Some(m);
+            }
+            // Try again from the character after the start of this attempt
+            let start = caps.get(0).unwrap().start();
+            from = start + s[start..].chars().next().map(char::len_utf8).unwrap_or(1);
+            if from > s.len() {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Find the closing mark of a constrained quote opened in `caps`. The text is as short
+    /// as possible, starts and ends with a non-space, and the closing mark is not followed
+    /// by a word character.
+    fn close_constrained<'a>(&self, s: &'a str, caps: &Captures<'a>) -> Option<QuoteMatch<'a>> {
+        let prefix = caps.get(1).unwrap();
+        let text_start = caps.get(0).unwrap().end();
+        if !s[text_start..].starts_with(|c: char| !c.is_whitespace()) {
+            return None;
+        }
+        let mut search = text_start;
+        while let Some(i) = s[search..].find(self.close) {
+            let close = search + i;
+            search = close + self.close.len();
+            // The text is at least one character
+            if close == text_start || s[..close].ends_with(char::is_whitespace) {
+                continue;
+            }
+            let after = s[search..].chars().next();
+            let followed = match self.ty {
+                QuoteType::Monospaced => after
+                    .map(|c| is_word(c) || "\"'`".contains(c))
+                    .unwrap_or(false),
+                _ => after.map(is_word).unwrap_or(false),
+            };
+            if followed {
+                continue;
+            }
+            // An escaped quote starts at the backslash, which is dropped later
+            let escaped = prefix.as_str() == "\\";
+            return Some(QuoteMatch {
+                start: match escaped {
+                    true => prefix.start(),
+                    false => prefix.end(),
+                },
+                end: search,
+                escape: escaped as usize,
+                attrs: caps.get(2).map(|a| a.as_str()),
+                text: &s[text_start..close],
+            });
+        }
+        None
+    }
+}
+
+/// An unconstrained quote: an optional escape of one or two backslashes, optional
+/// attributes, and the marks around the text
+fn unconstrained(mark: &str, text: &str) -> Regex {
+    Regex::new(&format!(
+        r"(\\{{1,2}})?(?:\[([^\]]+)\])?{}{}{}",
+        mark, text, mark
+    ))
+    .unwrap()
+}
+
+/// The opening of a constrained quote: a character that is not a word character (or the
+/// start of a line), optional attributes, and the opening mark. Characters in `excluded`
+/// may not precede the quote either.
+fn constrained(mark: &str, excluded: &str) -> Regex {
+    let excluded = regex::escape(excluded);
+    Regex::new(&format!(
+        r"(?m)(^|[^\w;:{}}}])(?:\[([^\]]+)\])?{}",
+        excluded, mark
+    ))
+    .unwrap()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Attributes of quoted text: the shorthand `[#id.role]`, or a role alone, `[role]`. Text
+/// after a comma is ignored.
+fn quote_attrs(s: &str) -> ElementAttrs {
+    let s = s.split(',').next().unwrap_or("").trim();
+    if s.starts_with(['.', '#']) {
+        let attrs = reader::ElementAttrs(vec![reader::ElementAttr::Positional(s.to_owned())]);
+        return ElementAttrs::from(attrs).with_shorthand();
+    }
+    let mut attrs = ElementAttrs::default();
+    if !s.is_empty() {
+        attrs.insert("role", s);
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::subs::apply_with;
+
+    fn quoted(ty: QuoteType, content: Vec<Inline>) -> Inline {
+        Inline::new(InlineKind::Quoted { ty, content })
+    }
+
+    fn text(s: &str) -> Inline {
+        Inline::unquoted(s)
+    }
+
+    #[test]
+    fn constrained_quotes() {
+        assert_eq!(
+            apply_with("a *strong* word", substitute_quotes),
+            vec![
+                text("a "),
+                quoted(QuoteType::Strong, vec![text("strong")]),
+                text(" word")
+            ]
+        );
+        assert_eq!(
+            apply_with("_emphasis_, `mono` and #mark#.", substitute_quotes),
+            vec![
+                quoted(QuoteType::Emphasis, vec![text("emphasis")]),
+                text(", "),
+                quoted(QuoteType::Monospaced, vec![text("mono")]),
+                text(" and "),
+                quoted(QuoteType::Mark, vec![text("mark")]),
+                text(".")
+            ]
+        );
+        assert_eq!(
+            apply_with("\"`double`\" and '`single`'", substitute_quotes),
+            vec![
+                quoted(QuoteType::Double, vec![text("double")]),
+                text(" and "),
+                quoted(QuoteType::Single, vec![text("single")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn word_boundaries() {
+        // Constrained quotes are not within words, and text does not start or end with spaces
+        for s in [
+            "snake_case_name",
+            "2*3*4",
+            "a * b * c",
+            "x_ y_",
+            "mail#me#now",
+        ] {
+            assert_eq!(apply_with(s, substitute_quotes), vec![text(s)], "{}", s);
+        }
+        // The closing mark extends past marks followed by a word character
+        assert_eq!(
+            apply_with("*a*b c*", substitute_quotes),
+            vec![quoted(QuoteType::Strong, vec![text("a*b c")])]
+        );
+    }
+
+    #[test]
+    fn unconstrained_quotes() {
+        assert_eq!(
+            apply_with("un**strong**ly, __em__ph and ``mo``no", substitute_quotes),
+            vec![
+                text("un"),
+                quoted(QuoteType::Strong, vec![text("strong")]),
+                text("ly, "),
+                quoted(QuoteType::Emphasis, vec![text("em")]),
+                text("ph and "),
+                quoted(QuoteType::Monospaced, vec![text("mo")]),
+                text("no")
+            ]
+        );
+        assert_eq!(
+            apply_with("E=mc^2^ and H~2~O", substitute_quotes),
+            vec![
+                text("E=mc"),
+                quoted(QuoteType::Superscript, vec![text("2")]),
+                text(" and H"),
+                quoted(QuoteType::Subscript, vec![text("2")]),
+                text("O")
+            ]
+        );
+        assert_eq!(
+            apply_with("a ^b c^ d", substitute_quotes),
+            vec![text("a ^b c^ d")]
+        );
+    }
+
+    #[test]
+    fn nested_quotes() {
+        assert_eq!(
+            apply_with("*bold _and em_*", substitute_quotes),
+            vec![quoted(
+                QuoteType::Strong,
+                vec![
+                    text("bold "),
+                    quoted(QuoteType::Emphasis, vec![text("and em")])
+                ]
+            )]
+        );
+        assert_eq!(
+            apply_with("`*code*`", substitute_quotes),
+            vec![quoted(
+                QuoteType::Monospaced,
+                vec![quoted(QuoteType::Strong, vec![text("code")])]
+            )]
+        );
+    }
+
+    #[test]
+    fn role_spans() {
+        let inlines = apply_with(
+            "a [.big.red]#span# and [small]##un##constrained",
+            substitute_quotes,
+        );
+        assert_eq!(inlines[1].attrs.roles(), vec!["big", "red"]);
+        assert!(matches!(
+            inlines[1].kind,
+            InlineKind::Quoted {
+                ty: QuoteType::Mark,
+                ..
+            }
+        ));
+        assert_eq!(inlines[3].attrs.roles(), vec!["small"]);
+        let inlines = apply_with("[#intro.lead]*text*", substitute_quotes);
+        assert_eq!(inlines[0].attrs.id(), Some("intro"));
+        assert_eq!(inlines[0].attrs.roles(), vec!["lead"]);
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        assert_eq!(
+            apply_with("\\*not strong*", substitute_quotes),
+            vec![text("*not strong*")]
+        );
+        assert_eq!(
+            apply_with("\\**not strong**", substitute_quotes),
+            vec![text("**not strong**")]
+        );
+        assert_eq!(
+            apply_with("\\\\__func__()", substitute_quotes),
+            vec![text("__func__()")]
+        );
+    }
+    #[test]
+    fn quoted_source() {
+        let source = "a *b* [.role]#c# \\_d_ ``e``";
+        let nodes = substitute_quotes(vec![Node::Text(source.to_owned())]);
+        // Nested content comes first
+        let sources = std::cell::RefCell::new(Vec::new());
+        Node::substitute(nodes, &|s| {
+            sources.borrow_mut().push(s.source(s));
+            Vec::new()
+        });
+        assert_eq!(sources.into_inner(), vec!["b", "c", "e", source]);
+    }
+}
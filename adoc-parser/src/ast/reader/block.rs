@@ -1,3 +1,8 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::parser::{Parser, ParserError};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Content {
     Compound,
@@ -32,4 +37,289 @@ pub enum Context {
     ThematicBreak,
     UnorderedList,
     Verse,
+    Video,
+}
+
+/// Type of an admonition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmonitionType {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AdmonitionType {
+    /// Type from its upper case label, as in `NOTE:` or `[NOTE]`
+    pub fn from_label(s: &str) -> Option<Self> {
+        match s {
+            "NOTE" => Some(Self::Note),
+            "TIP" => Some(Self::Tip),
+            "IMPORTANT" => Some(Self::Important),
+            "WARNING" => Some(Self::Warning),
+            "CAUTION" => Some(Self::Caution),
+            _ => None,
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Note => "NOTE",
+            Self::Tip => "TIP",
+            Self::Important => "IMPORTANT",
+            Self::Warning => "WARNING",
+            Self::Caution => "CAUTION",
+        }
+    }
+    /// Lower case name, used in the caption attribute, e.g. `note-caption`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+}
+
+/// The first line of an admonition paragraph, `NOTE: text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmonitionLine {
+    pub ty: AdmonitionType,
+    pub text: String,
+}
+
+impl Parser for AdmonitionLine {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^(NOTE|TIP|IMPORTANT|WARNING|CAUTION):\s+(\S.*)$").unwrap();
+        }
+        RE.captures(s)
+            .map(|caps| Self {
+                ty: AdmonitionType::from_label(&caps[1]).unwrap(),
+                text: caps[2].to_owned(),
+            })
+            .ok_or(ParserError::Mismatch)
+    }
+}
+
+/// A thematic break, `'''` or a Markdown rule such as `---` or `* * *`, or a page break,
+/// `<<<`. Markdown rules may be indented by up to three spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakLine {
+    Thematic,
+    Page,
+}
+
+impl Parser for BreakLine {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE_MD: Regex = Regex::new(r"^ {0,3}([-*_])( *)([-*_])( *)([-*_])$").unwrap();
+        }
+        let s = s.trim_end();
+        let repeated = |c| s.len() >= 3 && s.chars().all(|x| x == c);
+        if repeated('\'') {
+            return Ok(Self::Thematic);
+        }
+        if repeated('<') {
+            return Ok(Self::Page);
+        }
+        // The same character, equally spaced
+        match RE_MD.captures(s) {
+            Some(caps) if caps[1] == caps[3] && caps[1] == caps[5] && caps[2] == caps[4] => {
+                Ok(Self::Thematic)
+            }
+            _ => Err(ParserError::Mismatch),
+        }
+    }
+}
+
+/// A block title line, `.Title`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockTitle(pub String);
+
+impl Parser for BlockTitle {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^\.([^\s.].*)$").unwrap();
+        }
+        RE.captures(s)
+            .map(|caps| Self(caps[1].trim_end().to_owned()))
+            .ok_or(ParserError::Mismatch)
+    }
+}
+
+/// A block anchor line, `[[id]]` or `[[id, reftext]]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockAnchor {
+    pub id: String,
+    pub reftext: Option<String>,
+}
+
+impl Parser for BlockAnchor {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^\[\[([\p{L}_:][\p{L}\p{N}_:.\-]*)(?:,\s*(.+?))?\]\]$").unwrap();
+        }
+        RE.captures(s.trim_end())
+            .map(|caps| Self {
+                id: caps[1].to_owned(),
+                reftext: caps.get(2).map(|r| r.as_str().to_owned()),
+            })
+            .ok_or(ParserError::Mismatch)
+    }
+}
+
+/// A bibliography entry at the start of a list item, `[[[id]]] text` or
+/// `[[[id, label]]] text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibliographyEntry {
+    pub id: String,
+    pub label: Option<String>,
+    pub text: String,
+}
+
+impl Parser for BibliographyEntry {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(
+                r"^\[\[\[([\p{L}_:][\p{L}\p{N}_:.\-]*)(?:,\s*(.+?))?\]\]\](?:\s+|$)((?s).*)$"
+            )
+            .unwrap();
+        }
+        RE.captures(s)
+            .map(|caps| Self {
+                id: caps[1].to_owned(),
+                label: caps.get(2).map(|l| l.as_str().to_owned()),
+                text: caps[3].to_owned(),
+            })
+            .ok_or(ParserError::Mismatch)
+    }
+}
+
+/// A single line comment, `// comment`. Does not match a comment block delimiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineComment;
+
+impl Parser for LineComment {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        if s.starts_with("//") && !s.starts_with("///") {
+            Ok(Self)
+        } else {
+            Err(ParserError::Mismatch)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use claim::*;
+
+    #[test]
+    fn block_title() {
+        assert_ok_eq!(BlockTitle::parse(".Title"), BlockTitle("Title".to_owned()));
+        assert_ok_eq!(
+            BlockTitle::parse(".A longer title "),
+            BlockTitle("A longer title".to_owned())
+        );
+        assert!(BlockTitle::parse(".").unwrap_err().mismatches());
+        assert!(BlockTitle::parse(". Title").unwrap_err().mismatches());
+        assert!(BlockTitle::parse("....").unwrap_err().mismatches());
+        assert!(BlockTitle::parse("Title").unwrap_err().mismatches());
+    }
+
+    #[test]
+    fn block_anchor() {
+        assert_ok_eq!(
+            BlockAnchor::parse("[[intro]]"),
+            BlockAnchor {
+                id: "intro".to_owned(),
+                reftext: None
+            }
+        );
+        assert_ok_eq!(
+            BlockAnchor::parse("[[intro, The Introduction]]"),
+            BlockAnchor {
+                id: "intro".to_owned(),
+                reftext: Some("The Introduction".to_owned())
+            }
+        );
+        assert!(BlockAnchor::parse("[[[bib]]]").unwrap_err().mismatches());
+        assert!(BlockAnchor::parse("[[1st]]").unwrap_err().mismatches());
+        assert!(BlockAnchor::parse("[intro]").unwrap_err().mismatches());
+    }
+
+    #[test]
+    fn admonition_line() {
+        assert_ok_eq!(
+            AdmonitionLine::parse("NOTE: Read this"),
+            AdmonitionLine {
+                ty: AdmonitionType::Note,
+                text: "Read this".to_owned()
+            }
+        );
+        assert_ok!(AdmonitionLine::parse("CAUTION:  Hot"));
+        assert!(AdmonitionLine::parse("NOTE:text").unwrap_err().mismatches());
+        assert!(AdmonitionLine::parse("Note: text")
+            .unwrap_err()
+            .mismatches());
+        assert!(AdmonitionLine::parse("DANGER: text")
+            .unwrap_err()
+            .mismatches());
+    }
+
+    #[test]
+    fn break_line() {
+        for s in [
+            "'''", "''''", "---", "***", "___", "- - -", "* * *", "-  -  -", "'''  ", " ---",
+            "   * * *",
+        ] {
+            assert_ok_eq!(BreakLine::parse(s), BreakLine::Thematic);
+        }
+        assert_ok_eq!(BreakLine::parse("<<<"), BreakLine::Page);
+        assert_ok_eq!(BreakLine::parse("<<<<"), BreakLine::Page);
+        for s in [
+            "----", "****", "- -", "-- -", "- * -", "- -  -", "    ---", " '''", "<<",
+        ] {
+            assert!(BreakLine::parse(s).unwrap_err().mismatches());
+        }
+    }
+
+    #[test]
+    fn bibliography_entry() {
+        assert_ok_eq!(
+            BibliographyEntry::parse("[[[pp]]] The Pragmatic Programmer"),
+            BibliographyEntry {
+                id: "pp".to_owned(),
+                label: None,
+                text: "The Pragmatic Programmer".to_owned()
+            }
+        );
+        assert_ok_eq!(
+            BibliographyEntry::parse("[[[gof, GoF]]] Design\nPatterns"),
+            BibliographyEntry {
+                id: "gof".to_owned(),
+                label: Some("GoF".to_owned()),
+                text: "Design\nPatterns".to_owned()
+            }
+        );
+        assert!(BibliographyEntry::parse("[[pp]] text")
+            .unwrap_err()
+            .mismatches());
+        assert!(BibliographyEntry::parse("[[[pp]]]text")
+            .unwrap_err()
+            .mismatches());
+    }
+
+    #[test]
+    fn line_comment() {
+        assert_ok!(LineComment::parse("//"));
+        assert_ok!(LineComment::parse("// comment"));
+        assert!(LineComment::parse("////").unwrap_err().mismatches());
+        assert!(LineComment::parse("/ comment").unwrap_err().mismatches());
+    }
 }
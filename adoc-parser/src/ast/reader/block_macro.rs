@@ -0,0 +1,78 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    element_attr::ElementAttrs,
+    parser::{Parser, ParserError},
+};
+
+/// A block macro line, `name::target[attrs]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMacro {
+    pub name: String,
+    pub target: String,
+    pub attrs: ElementAttrs,
+}
+
+impl Parser for BlockMacro {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^([a-zA-Z][\w\-]*)::(\S|\S.*?\S)?\[(.*)\]$").unwrap();
+        }
+        let caps = RE.captures(s.trim_end()).ok_or(ParserError::Mismatch)?;
+        let attrs = match &caps[3] {
+            // Leading whitespace is not allowed in attribute lists
+            attrs if attrs.starts_with(char::is_whitespace) => {
+                return Err(ParserError::fail("invalid macro attributes"))
+            }
+            attrs => ElementAttrs::parse(&format!("[{}]", attrs))?,
+        };
+        Ok(Self {
+            name: caps[1].to_owned(),
+            target: caps.get(2).map(|t| t.as_str()).unwrap_or("").to_owned(),
+            attrs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::reader::ElementAttr;
+    use claim::*;
+
+    #[test]
+    fn block_macro() {
+        assert_ok_eq!(
+            BlockMacro::parse("image::sunset.jpg[Sunset, 300, 200]"),
+            BlockMacro {
+                name: "image".to_owned(),
+                target: "sunset.jpg".to_owned(),
+                attrs: ElementAttrs(vec![
+                    ElementAttr::Positional("Sunset".to_owned()),
+                    ElementAttr::Positional("300".to_owned()),
+                    ElementAttr::Positional("200".to_owned()),
+                ]),
+            }
+        );
+        assert_ok_eq!(
+            BlockMacro::parse("toc::[]"),
+            BlockMacro {
+                name: "toc".to_owned(),
+                target: String::new(),
+                attrs: ElementAttrs(vec![]),
+            }
+        );
+        assert!(BlockMacro::parse("image:inline.png[]")
+            .unwrap_err()
+            .mismatches());
+        assert!(BlockMacro::parse("image::a.png").unwrap_err().mismatches());
+        assert!(BlockMacro::parse("text image::a.png[]")
+            .unwrap_err()
+            .mismatches());
+        assert!(!BlockMacro::parse("image::a.png[ x]")
+            .unwrap_err()
+            .mismatches());
+    }
+}
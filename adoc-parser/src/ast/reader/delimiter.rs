@@ -10,6 +10,9 @@ pub enum Delimiter {
     NonTable(char, usize),
     /// A table block, (table delimiter, length). Length includes delimiter.
     Table(char, usize),
+    /// A Markdown-style fenced code block, with an optional language. Closed by three
+    /// backticks.
+    Fenced(Option<String>),
 }
 
 impl Delimiter {
@@ -19,6 +22,7 @@ impl Delimiter {
             static ref RE: Regex = Regex::new(r"^[/=\-.*+_]{4,}$").unwrap();
             // Tables
             static ref RE_T: Regex = Regex::new(r"^[|,:!]={3,}$").unwrap();
+            static ref RE_F: Regex = Regex::new(r"^```([\w+\-.#]*)$").unwrap();
         }
         if s == "--" {
             Some(Self::Open)
@@ -27,7 +31,15 @@ impl Delimiter {
         } else if RE_T.is_match(s) {
             Some(Self::Table(s.chars().next().unwrap(), s.len()))
         } else {
-            None
+            RE_F.captures(s)
+                .map(|caps| Self::Fenced(Some(caps[1].to_owned()).filter(|lang| !lang.is_empty())))
+        }
+    }
+    /// Whether `s` closes a block opened by this delimiter. Input must be stripped.
+    pub fn is_closed_by(&self, opening: &str, s: &str) -> bool {
+        match self {
+            Delimiter::Fenced(_) => s == "```",
+            _ => s == opening,
         }
     }
     /// Default context and content of delimiter
@@ -35,6 +47,7 @@ impl Delimiter {
         match self {
             Delimiter::Open => (Some(Context::Open), Some(Content::Compound)),
             Delimiter::Table(_, _) => (Some(Context::Table), Some(Content::Table)),
+            Delimiter::Fenced(_) => (Some(Context::Listing), Some(Content::Verbatim)),
             // Comment
             Delimiter::NonTable('/', _) => (None, None),
             // Example
@@ -53,3 +66,28 @@ impl Delimiter {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(Delimiter::parse("--"), Some(Delimiter::Open));
+        assert_eq!(Delimiter::parse("----"), Some(Delimiter::NonTable('-', 4)));
+        assert_eq!(Delimiter::parse("!===="), Some(Delimiter::Table('!', 5)));
+        assert_eq!(Delimiter::parse("---"), None);
+        assert_eq!(Delimiter::parse("|=="), None);
+    }
+
+    #[test]
+    fn fenced_delimiters() {
+        assert_eq!(Delimiter::parse("```"), Some(Delimiter::Fenced(None)));
+        let rust = Delimiter::parse("```rust").unwrap();
+        assert_eq!(rust, Delimiter::Fenced(Some("rust".to_owned())));
+        assert!(rust.is_closed_by("```rust", "```"));
+        assert!(!rust.is_closed_by("```rust", "```rust"));
+        assert_eq!(Delimiter::parse("``"), None);
+        assert_eq!(Delimiter::parse("```a b"), None);
+    }
+}
@@ -1,8 +1,10 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use super::parser::{Parser, ParserError};
+
 #[derive(Debug, Clone)]
-pub struct DocumentAttrName(String);
+pub struct DocumentAttrName(pub String);
 
 impl DocumentAttrName {
     pub fn parse(s: &str) -> Option<Self> {
@@ -14,11 +16,11 @@ impl DocumentAttrName {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DocumentAttrValue {
-    ValueLine(String),
-    ValuePartialLine(String),
-    ValuePartialLineHardBreak(String),
+    Line(String),
+    PartialLine(String),
+    PartialLineHardBreak(String),
 }
 
 impl DocumentAttrValue {
@@ -28,13 +30,94 @@ impl DocumentAttrValue {
             static ref RE_LB: Regex = Regex::new(r"(.*)\s+\\$").unwrap();
         }
         if let Some(caps) = RE_HB.captures(s) {
-            Some(Self::ValuePartialLineHardBreak(caps[1].to_owned()))
+            Some(Self::PartialLineHardBreak(caps[1].to_owned()))
         } else if let Some(caps) = RE_LB.captures(s) {
-            Some(Self::ValuePartialLine(caps[1].to_owned()))
-        } else if s.len() != 0 {
-            Some(Self::ValueLine(s.to_owned()))
+            Some(Self::PartialLine(caps[1].to_owned()))
+        } else if !s.is_empty() {
+            Some(Self::Line(s.to_owned()))
         } else {
             None
         }
     }
 }
+
+/// An attribute entry line, `:name: value`. The attribute is unset by `:name!:` or `:!name:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentAttrEntry {
+    pub name: String,
+    pub unset: bool,
+    pub value: Option<DocumentAttrValue>,
+}
+
+impl Parser for DocumentAttrEntry {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^:(!)?([a-zA-Z0-9_][a-zA-Z0-9_\-]*)(!)?:(?:\s+(.*))?$").unwrap();
+        }
+        let caps = RE.captures(s).ok_or(ParserError::Mismatch)?;
+        Ok(Self {
+            name: caps[2].to_ascii_lowercase(),
+            unset: caps.get(1).is_some() || caps.get(3).is_some(),
+            value: caps
+                .get(4)
+                .and_then(|v| DocumentAttrValue::parse(v.as_str().trim_end())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use claim::*;
+
+    #[test]
+    fn not_an_attr_entry() {
+        let test = |s| assert_eq!(DocumentAttrEntry::parse(s), Err(ParserError::Mismatch));
+
+        test("");
+        test(":");
+        test("::");
+        test(": name:");
+        test(":name");
+        test("name: value");
+        test(":name:value");
+    }
+
+    #[test]
+    fn valid_attr_entry() {
+        let test = |s, name: &str, unset, value: Option<&str>| {
+            assert_ok_eq!(
+                DocumentAttrEntry::parse(s),
+                DocumentAttrEntry {
+                    name: name.to_owned(),
+                    unset,
+                    value: value.map(|v| DocumentAttrValue::Line(v.to_owned())),
+                }
+            )
+        };
+        test(":name:", "name", false, None);
+        test(":Name: value", "name", false, Some("value"));
+        test(":name:   value  ", "name", false, Some("value"));
+        test(":name!:", "name", true, None);
+        test(":!name:", "name", true, None);
+        test(
+            ":note-caption: Hinweis",
+            "note-caption",
+            false,
+            Some("Hinweis"),
+        );
+    }
+
+    #[test]
+    fn continued_attr_entry() {
+        assert_ok_eq!(
+            DocumentAttrEntry::parse(":desc: first \\"),
+            DocumentAttrEntry {
+                name: "desc".to_owned(),
+                unset: false,
+                value: Some(DocumentAttrValue::PartialLine("first".to_owned())),
+            }
+        );
+    }
+}
@@ -100,6 +100,23 @@ mod test {
         test("[\"pos0\"]", vec!["pos0"]);
         test("[\"pos0,pos1\"]", vec!["pos0,pos1"]);
         test("[\"pos0,pos1]\"]", vec!["pos0,pos1]"]);
+        test(
+            "[quote, Albert Einstein ]",
+            vec!["quote", "Albert Einstein"],
+        );
+        test("[quote, Captain's Log]", vec!["quote", "Captain's Log"]);
+    }
+
+    #[test]
+    fn named_attrs() {
+        assert_ok_eq!(
+            ElementAttrs::parse("[quote, attribution=Albert Einstein, citetitle=\"On Physics\"]"),
+            ElementAttrs(vec![
+                ElementAttr::Positional("quote".to_owned()),
+                ElementAttr::Named("attribution".to_owned(), "Albert Einstein".to_owned()),
+                ElementAttr::Named("citetitle".to_owned(), "On Physics".to_owned()),
+            ])
+        );
     }
     // TODO test named attrs and mixed attrs
 }
@@ -123,9 +140,8 @@ impl ElementAttr {
             return Err(ParserError::fail("attribute is empty"));
         }
         // Look for an equal sign
-        scanner.whitespaces();
+        let space = scanner.whitespaces();
         let eq = scanner.equal();
-        scanner.whitespaces();
         if eq.is_some() {
             // If we encounter an equal sign, expect a named attribute
             scanner.whitespaces();
@@ -134,8 +150,16 @@ impl ElementAttr {
                 .map(|v| ElementAttr::Named(first, v))
                 .or(Err(ParserError::fail("invalid value")))
         } else {
-            // Otherwise, expect positional
-            Ok(ElementAttr::Positional(first))
+            // Otherwise, expect positional, which may contain spaces
+            let rest = scanner.unquoted_string();
+            match (space, rest.is_empty()) {
+                (_, true) => Ok(ElementAttr::Positional(first)),
+                (Some(space), false) => Ok(ElementAttr::Positional(format!(
+                    "{}{}{}",
+                    first, space, rest
+                ))),
+                (None, false) => Ok(ElementAttr::Positional(first + &rest)),
+            }
         }
     }
 }
@@ -212,7 +236,7 @@ impl<'a> ElementAttrScanner<'a> {
     /// A character in an unquoted string
     /// TODO: line-based can use ] too
     fn unquoted_char(&mut self) -> Option<char> {
-        self.next_if(|c| !",'\"]".contains(c) && !c.is_whitespace())
+        self.next_if(|c| !",]".contains(c))
     }
     fn name_char(&mut self) -> Option<char> {
         self.next_if(|c| !",'\"]=".contains(c) && !c.is_whitespace())
@@ -260,17 +284,19 @@ impl<'a> ElementAttrScanner<'a> {
         }
         Ok(s)
     }
+    /// An unquoted string may contain spaces. Trailing spaces are dropped.
     /// TODO: type level guarantees
     fn unquoted_string(&mut self) -> String {
         let mut s = String::new();
         while let Some(c) = self.unquoted_char() {
             s.push(c);
         }
+        s.truncate(s.trim_end().len());
         s
     }
     fn name_string(&mut self) -> String {
         let mut s = String::new();
-        while let Some(c) = self.unquoted_char() {
+        while let Some(c) = self.name_char() {
             s.push(c);
         }
         s
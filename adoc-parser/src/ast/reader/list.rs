@@ -0,0 +1,104 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::parser::{Parser, ParserError};
+
+/// A list item marker. Items with the same marker belong to the same list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListMarker {
+    /// `-`, or `*` to `*****`
+    Unordered(String),
+    /// `.` to `.....`. Numbered markers such as `1.` are normalized to `.`.
+    Ordered(String),
+}
+
+/// The first line of a list item, `* text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItemLine {
+    pub marker: ListMarker,
+    pub text: String,
+}
+
+impl Parser for ListItemLine {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^\s*(-|\*{1,5}|\.{1,5}|\d+\.)\s+(\S.*)$").unwrap();
+        }
+        let caps = RE.captures(s).ok_or(ParserError::Mismatch)?;
+        let marker = &caps[1];
+        let marker = if marker.starts_with(|c: char| c.is_ascii_digit()) {
+            ListMarker::Ordered(".".to_owned())
+        } else if marker.starts_with('.') {
+            ListMarker::Ordered(marker.to_owned())
+        } else {
+            ListMarker::Unordered(marker.to_owned())
+        };
+        Ok(Self {
+            marker,
+            text: caps[2].trim_end().to_owned(),
+        })
+    }
+}
+
+/// A list continuation line, `+`, which attaches the following block to a list item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListContinuation;
+
+impl Parser for ListContinuation {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        match s.trim_end() {
+            "+" => Ok(Self),
+            _ => Err(ParserError::Mismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use claim::*;
+
+    #[test]
+    fn not_a_list_item() {
+        let test = |s| assert_eq!(ListItemLine::parse(s), Err(ParserError::Mismatch));
+
+        test("");
+        test("*");
+        test("* ");
+        test("*text");
+        test(".Title");
+        test("****");
+        test("****** six");
+        test("--");
+        test("1.5 million");
+    }
+
+    #[test]
+    fn valid_list_item() {
+        let test = |s, marker: ListMarker, text: &str| {
+            assert_ok_eq!(
+                ListItemLine::parse(s),
+                ListItemLine {
+                    marker,
+                    text: text.to_owned()
+                }
+            )
+        };
+        let unordered = |m: &str| ListMarker::Unordered(m.to_owned());
+        let ordered = |m: &str| ListMarker::Ordered(m.to_owned());
+        test("* item", unordered("*"), "item");
+        test("  ** item ", unordered("**"), "item");
+        test("- item", unordered("-"), "item");
+        test(". item", ordered("."), "item");
+        test("... item", ordered("..."), "item");
+        test("10. item", ordered("."), "item");
+    }
+
+    #[test]
+    fn list_continuation() {
+        assert_ok!(ListContinuation::parse("+"));
+        assert_ok!(ListContinuation::parse("+ "));
+        assert!(ListContinuation::parse("++").unwrap_err().mismatches());
+        assert!(ListContinuation::parse(" +").unwrap_err().mismatches());
+    }
+}
@@ -1,13 +1,19 @@
 mod block;
+mod block_macro;
 mod delimiter;
 mod doc_attr;
 mod element_attr;
+mod list;
 mod parser;
 mod section;
+mod table;
 
 pub use block::*;
+pub use block_macro::*;
 pub use delimiter::*;
 pub use doc_attr::*;
 pub use element_attr::*;
+pub use list::*;
 pub use parser::*;
 pub use section::*;
+pub use table::*;
@@ -55,7 +55,7 @@ mod test {
         let test = |marker: &str, level, title: &str| {
             let marker = marker.repeat(level + 1);
             assert_ok_eq!(
-                SectionTitle::parse(&vec![&marker[..], title].join(" ")),
+                SectionTitle::parse(&[&marker[..], title].join(" ")),
                 SectionTitle {
                     marker: marker.chars().next().unwrap(),
                     level,
@@ -64,7 +64,7 @@ mod test {
             );
         };
         let test_all = |title| {
-            for marker in vec!["#", "="] {
+            for marker in ["#", "="] {
                 for level in 0..5 {
                     test(marker, level, title);
                 }
@@ -0,0 +1,620 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::parser::{Parser, ParserError};
+
+/// Data format of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Prefix-separated values, `|cell`
+    Psv,
+    /// Comma-separated values, as in RFC 4180
+    Csv,
+    /// Delimiter-separated values, `cell:cell`
+    Dsv,
+    /// Tab-separated values
+    Tsv,
+}
+
+impl TableFormat {
+    /// Format from the `format` attribute
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "psv" => Some(Self::Psv),
+            "csv" => Some(Self::Csv),
+            "dsv" => Some(Self::Dsv),
+            "tsv" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+    /// Format implied by the first character of the table delimiter
+    pub fn from_delimiter(c: char) -> Self {
+        match c {
+            ',' => Self::Csv,
+            ':' => Self::Dsv,
+            _ => Self::Psv,
+        }
+    }
+    /// Default separator. A PSV table uses the first character of its delimiter.
+    pub fn default_separator(&self, delimiter: char) -> char {
+        match self {
+            Self::Psv => match delimiter {
+                '!' => '!',
+                _ => '|',
+            },
+            Self::Csv => ',',
+            Self::Dsv => ':',
+            Self::Tsv => '\t',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HorizontalAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl HorizontalAlign {
+    fn from_char(c: &str) -> Self {
+        match c {
+            "^" => Self::Center,
+            ">" => Self::Right,
+            _ => Self::Left,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VerticalAlign {
+    fn from_char(c: &str) -> Self {
+        match c {
+            "^" => Self::Middle,
+            ">" => Self::Bottom,
+            _ => Self::Top,
+        }
+    }
+}
+
+/// Cell style operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellStyle {
+    /// `a`, parsed as blocks
+    AsciiDoc,
+    /// `d`
+    Default,
+    /// `e`
+    Emphasis,
+    /// `h`
+    Header,
+    /// `l`
+    Literal,
+    /// `m`
+    Monospaced,
+    /// `s`
+    Strong,
+    /// `v`
+    Verse,
+}
+
+impl CellStyle {
+    fn from_char(c: &str) -> Self {
+        match c {
+            "a" => Self::AsciiDoc,
+            "e" => Self::Emphasis,
+            "h" => Self::Header,
+            "l" => Self::Literal,
+            "m" => Self::Monospaced,
+            "s" => Self::Strong,
+            "v" => Self::Verse,
+            _ => Self::Default,
+        }
+    }
+}
+
+/// The specifier before a cell separator, such as `2+^.>a`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellSpec {
+    pub colspan: usize,
+    pub rowspan: usize,
+    /// Repeat the cell this many times, `3*`
+    pub duplicate: usize,
+    pub halign: Option<HorizontalAlign>,
+    pub valign: Option<VerticalAlign>,
+    pub style: Option<CellStyle>,
+}
+
+impl Default for CellSpec {
+    fn default() -> Self {
+        Self {
+            colspan: 1,
+            rowspan: 1,
+            duplicate: 1,
+            halign: None,
+            valign: None,
+            style: None,
+        }
+    }
+}
+
+impl Parser for CellSpec {
+    fn parse(s: &str) -> Result<Self, ParserError> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^(?:(\d+)?(?:\.(\d+))?([*+]))?([<^>])?(?:\.([<^>]))?([adehlmsv])?$")
+                    .unwrap();
+        }
+        let caps = RE.captures(s).ok_or(ParserError::Mismatch)?;
+        if s.is_empty() || (caps.get(3).is_some() && caps.get(1).is_none() && caps.get(2).is_none())
+        {
+            return Err(ParserError::Mismatch);
+        }
+        let number = |i| caps.get(i).map(|n| n.as_str().parse::<usize>());
+        let mut spec = Self::default();
+        match caps.get(3).map(|op| op.as_str()) {
+            Some("*") => {
+                spec.duplicate = number(1)
+                    .unwrap_or(Ok(1))
+                    .map_err(|_| ParserError::fail("invalid duplication factor"))?;
+            }
+            Some(_) => {
+                spec.colspan = number(1)
+                    .unwrap_or(Ok(1))
+                    .map_err(|_| ParserError::fail("invalid column span"))?;
+                spec.rowspan = number(2)
+                    .unwrap_or(Ok(1))
+                    .map_err(|_| ParserError::fail("invalid row span"))?;
+            }
+            None => {}
+        }
+        spec.halign = caps.get(4).map(|c| HorizontalAlign::from_char(c.as_str()));
+        spec.valign = caps.get(5).map(|c| VerticalAlign::from_char(c.as_str()));
+        spec.style = caps.get(6).map(|c| CellStyle::from_char(c.as_str()));
+        Ok(spec)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnWidth {
+    /// Relative to the other columns
+    Proportional(usize),
+    Percentage(usize),
+    /// `~`, sized to its content
+    Auto,
+}
+
+/// One entry of the `cols` attribute, such as `2*^.>3a`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnSpec {
+    pub halign: Option<HorizontalAlign>,
+    pub valign: Option<VerticalAlign>,
+    pub width: ColumnWidth,
+    pub style: Option<CellStyle>,
+}
+
+impl Default for ColumnSpec {
+    fn default() -> Self {
+        Self {
+            halign: None,
+            valign: None,
+            width: ColumnWidth::Proportional(1),
+            style: None,
+        }
+    }
+}
+
+impl ColumnSpec {
+    /// Parse the whole `cols` attribute. A single number is a column count.
+    pub fn parse_cols(s: &str) -> Result<Vec<Self>, ParserError> {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<usize>() {
+            return Ok(vec![Self::default(); n]);
+        }
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^(?:(\d+)\*)?([<^>])?(?:\.([<^>]))?(\d+%?|~)?([adehlmsv])?$").unwrap();
+        }
+        let mut cols = Vec::new();
+        for entry in s.split([',', ';']) {
+            let caps = RE
+                .captures(entry.trim())
+                .ok_or_else(|| ParserError::fail("invalid column specifier"))?;
+            let repeat = caps
+                .get(1)
+                .map(|n| n.as_str().parse::<usize>())
+                .unwrap_or(Ok(1))
+                .map_err(|_| ParserError::fail("invalid column multiplier"))?;
+            let width = match caps.get(4).map(|w| w.as_str()) {
+                None => ColumnWidth::Proportional(1),
+                Some("~") => ColumnWidth::Auto,
+                Some(w) => match w.strip_suffix('%') {
+                    Some(p) => ColumnWidth::Percentage(p.parse().unwrap_or(0)),
+                    None => ColumnWidth::Proportional(w.parse().unwrap_or(1)),
+                },
+            };
+            let spec = Self {
+                halign: caps.get(2).map(|c| HorizontalAlign::from_char(c.as_str())),
+                valign: caps.get(3).map(|c| VerticalAlign::from_char(c.as_str())),
+                width,
+                style: caps.get(5).map(|c| CellStyle::from_char(c.as_str())),
+            };
+            cols.extend(std::iter::repeat_n(spec, repeat));
+        }
+        Ok(cols)
+    }
+}
+
+/// A cell as it appears in the source, before rows are formed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawCell {
+    pub spec: CellSpec,
+    pub text: String,
+    /// Index of the line where the text starts, relative to the table content
+    pub line: usize,
+    /// Index of the character where the text starts within that line
+    pub column: usize,
+}
+
+impl RawCell {
+    /// A CSV or DSV cell. Unquoted text is trimmed.
+    fn field(text: String, quoted: bool, line: usize, column: usize) -> Self {
+        let (text, column) = match quoted {
+            true => (text, column),
+            false => {
+                let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+                (text.trim().to_owned(), column + leading)
+            }
+        };
+        Self {
+            spec: CellSpec::default(),
+            text,
+            line,
+            column,
+        }
+    }
+}
+
+/// Cells split from table lines. Cells are grouped by the line (PSV) or record (CSV and
+/// DSV) where they start, along with the index of that line.
+pub fn split_cells(
+    lines: &[String],
+    format: TableFormat,
+    separator: char,
+) -> Vec<(usize, Vec<RawCell>)> {
+    match format {
+        TableFormat::Psv => split_psv(lines, separator),
+        TableFormat::Csv | TableFormat::Tsv => split_csv(lines, separator),
+        TableFormat::Dsv => split_dsv(lines, separator),
+    }
+}
+
+/// Split a spec from the end of the text preceding a separator. A spec must start the line
+/// or follow whitespace.
+fn split_spec(text: &str, line_start: bool) -> (&str, Option<CellSpec>) {
+    if text.ends_with(char::is_whitespace) {
+        return (text, None);
+    }
+    let start = text
+        .rfind(char::is_whitespace)
+        .map(|i| i + text[i..].chars().next().unwrap().len_utf8());
+    let start = match start {
+        Some(start) => start,
+        None if line_start => 0,
+        None => return (text, None),
+    };
+    match CellSpec::parse(&text[start..]) {
+        Ok(spec) => (&text[..start], Some(spec)),
+        Err(_) => (text, None),
+    }
+}
+
+fn split_psv(lines: &[String], separator: char) -> Vec<(usize, Vec<RawCell>)> {
+    let mut groups: Vec<(usize, Vec<RawCell>)> = Vec::new();
+    let mut current: Option<RawCell> = None;
+    for (n, line) in lines.iter().enumerate() {
+        let mut text = String::new();
+        let mut line_start = true;
+        let mut chars = line.chars().enumerate().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c == '\\' && chars.peek().map(|(_, c)| *c) == Some(separator) {
+                text.push(separator);
+                chars.next();
+            } else if c == separator {
+                let (rest, spec) = split_spec(&text, line_start);
+                if let Some(mut cell) = current.take() {
+                    cell.text.push_str(rest);
+                    groups.last_mut().unwrap().1.push(cell);
+                }
+                if groups.last().map(|(l, _)| *l != n).unwrap_or(true) {
+                    groups.push((n, Vec::new()));
+                }
+                current = Some(RawCell {
+                    spec: spec.unwrap_or_default(),
+                    text: String::new(),
+                    line: n,
+                    column: i + 1,
+                });
+                text.clear();
+                line_start = false;
+            } else {
+                text.push(c);
+            }
+        }
+        // Text before the first cell is dropped
+        if let Some(cell) = current.as_mut() {
+            cell.text.push_str(&text);
+            cell.text.push('\n');
+        }
+    }
+    if let Some(cell) = current {
+        groups.last_mut().unwrap().1.push(cell);
+    }
+    groups
+}
+
+fn split_csv(lines: &[String], separator: char) -> Vec<(usize, Vec<RawCell>)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    // Start of the record, and of the current field
+    let mut start = 0;
+    let mut field_start = (0, 0);
+    for (n, line) in lines.iter().enumerate() {
+        if !in_quotes {
+            if line.trim().is_empty() {
+                continue;
+            }
+            start = n;
+            field_start = (n, 0);
+        }
+        let mut chars = line.chars().enumerate().peekable();
+        while let Some((i, c)) = chars.next() {
+            if in_quotes {
+                if c == '"' {
+                    if chars.peek().map(|(_, c)| *c) == Some('"') {
+                        field.push('"');
+                        chars.next();
+                    } else {
+                        in_quotes = false;
+                    }
+                } else {
+                    field.push(c);
+                }
+            } else if c == '"' && field.trim().is_empty() {
+                field.clear();
+                in_quotes = true;
+                quoted = true;
+                field_start = (n, i + 1);
+            } else if c == separator {
+                let text = std::mem::take(&mut field);
+                record.push(RawCell::field(text, quoted, field_start.0, field_start.1));
+                quoted = false;
+                field_start = (n, i + 1);
+            } else if !(quoted && c.is_whitespace()) {
+                // Whitespace after a closing quote is dropped
+                field.push(c);
+            }
+        }
+        if in_quotes {
+            field.push('\n');
+        } else {
+            let text = std::mem::take(&mut field);
+            record.push(RawCell::field(text, quoted, field_start.0, field_start.1));
+            quoted = false;
+            records.push((start, std::mem::take(&mut record)));
+        }
+    }
+    // Unterminated quote
+    if in_quotes {
+        record.push(RawCell::field(field, quoted, field_start.0, field_start.1));
+        records.push((start, record));
+    }
+    records
+}
+
+fn split_dsv(lines: &[String], separator: char) -> Vec<(usize, Vec<RawCell>)> {
+    let mut records = Vec::new();
+    for (n, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut field_start = 0;
+        let mut chars = line.chars().enumerate().peekable();
+        loop {
+            match chars.next() {
+                Some((_, '\\')) if chars.peek().map(|(_, c)| *c) == Some(separator) => {
+                    field.push(separator);
+                    chars.next();
+                }
+                Some((_, c)) if c != separator => field.push(c),
+                next => {
+                    let text = std::mem::take(&mut field);
+                    record.push(RawCell::field(text, false, n, field_start));
+                    match next {
+                        Some((i, _)) => field_start = i + 1,
+                        None => break,
+                    }
+                }
+            }
+        }
+        records.push((n, record));
+    }
+    records
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use claim::*;
+
+    fn lines(s: &str) -> Vec<String> {
+        s.lines().map(str::to_owned).collect()
+    }
+
+    fn texts(groups: Vec<(usize, Vec<RawCell>)>) -> Vec<(usize, Vec<String>)> {
+        groups
+            .into_iter()
+            .map(|(n, cells)| (n, cells.into_iter().map(|c| c.text).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn not_a_cell_spec() {
+        let test = |s| assert_eq!(CellSpec::parse(s), Err(ParserError::Mismatch));
+
+        test("");
+        test("+");
+        test("*");
+        test("x");
+        test("aa");
+        test("2");
+        test("2+a ");
+    }
+
+    #[test]
+    fn valid_cell_spec() {
+        assert_ok_eq!(
+            CellSpec::parse("a"),
+            CellSpec {
+                style: Some(CellStyle::AsciiDoc),
+                ..Default::default()
+            }
+        );
+        assert_ok_eq!(
+            CellSpec::parse("2+"),
+            CellSpec {
+                colspan: 2,
+                ..Default::default()
+            }
+        );
+        assert_ok_eq!(
+            CellSpec::parse(".3+"),
+            CellSpec {
+                rowspan: 3,
+                ..Default::default()
+            }
+        );
+        assert_ok_eq!(
+            CellSpec::parse("2.3+^.>l"),
+            CellSpec {
+                colspan: 2,
+                rowspan: 3,
+                halign: Some(HorizontalAlign::Center),
+                valign: Some(VerticalAlign::Bottom),
+                style: Some(CellStyle::Literal),
+                ..Default::default()
+            }
+        );
+        assert_ok_eq!(
+            CellSpec::parse("3*"),
+            CellSpec {
+                duplicate: 3,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn cols_attribute() {
+        assert_ok_eq!(ColumnSpec::parse_cols("3"), vec![ColumnSpec::default(); 3]);
+        let cols = ColumnSpec::parse_cols("1,2a, 3*>~").unwrap();
+        assert_eq!(cols.len(), 5);
+        assert_eq!(cols[1].width, ColumnWidth::Proportional(2));
+        assert_eq!(cols[1].style, Some(CellStyle::AsciiDoc));
+        assert_eq!(cols[4].width, ColumnWidth::Auto);
+        assert_eq!(cols[4].halign, Some(HorizontalAlign::Right));
+        assert_ok_eq!(
+            ColumnSpec::parse_cols("25%;75%"),
+            vec![
+                ColumnSpec {
+                    width: ColumnWidth::Percentage(25),
+                    ..Default::default()
+                },
+                ColumnSpec {
+                    width: ColumnWidth::Percentage(75),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_err!(ColumnSpec::parse_cols("1,x"));
+    }
+
+    #[test]
+    fn psv_cells() {
+        let groups = split_cells(&lines("|a |b\n\n|c\nd |e \\| f"), TableFormat::Psv, '|');
+        assert_eq!(
+            texts(groups),
+            vec![
+                (0, vec!["a ".to_owned(), "b\n\n".to_owned()]),
+                (2, vec!["c\nd ".to_owned()]),
+                (3, vec!["e | f\n".to_owned()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn psv_cell_specs() {
+        let groups = split_cells(&lines("2+|a s|b\na|c|d"), TableFormat::Psv, '|');
+        let specs: Vec<_> = groups
+            .iter()
+            .flat_map(|(_, cells)| cells.iter().map(|c| (c.spec.colspan, c.spec.style)))
+            .collect();
+        assert_eq!(
+            specs,
+            vec![
+                (2, None),
+                (1, Some(CellStyle::Strong)),
+                (1, Some(CellStyle::AsciiDoc)),
+                (1, None),
+            ]
+        );
+        // A spec must follow whitespace
+        assert_eq!(texts(groups)[1].1, vec!["c".to_owned(), "d\n".to_owned()]);
+    }
+
+    #[test]
+    fn csv_cells() {
+        let groups = split_cells(
+            &lines("a, \"b, c\" ,d\n\n\"multi\nline\",\"say \"\"hi\"\"\",e"),
+            TableFormat::Csv,
+            ',',
+        );
+        assert_eq!(
+            texts(groups),
+            vec![
+                (0, vec!["a".to_owned(), "b, c".to_owned(), "d".to_owned()]),
+                (
+                    2,
+                    vec![
+                        "multi\nline".to_owned(),
+                        "say \"hi\"".to_owned(),
+                        "e".to_owned()
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dsv_cells() {
+        let groups = split_cells(&lines("a:b\\:c: d\n\ne:f"), TableFormat::Dsv, ':');
+        assert_eq!(
+            texts(groups),
+            vec![
+                (0, vec!["a".to_owned(), "b:c".to_owned(), "d".to_owned()]),
+                (2, vec!["e".to_owned(), "f".to_owned()]),
+            ]
+        );
+    }
+}
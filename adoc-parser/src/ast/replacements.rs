@@ -0,0 +1,242 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    inline::{Inline, InlineKind},
+    subs::{replace_all, Node},
+};
+
+/// A typographic replacement, or a character reference passed through as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replacement {
+    /// `(C)`
+    Copyright,
+    /// `(R)`
+    Registered,
+    /// `(TM)`
+    Trademark,
+    /// `--` surrounded by spaces, which it replaces, or between words. Spaced, it has thin
+    /// spaces around it; between words, a zero width space after it, to allow a line break.
+    EmDash { spaced: bool },
+    /// `...`
+    Ellipsis,
+    /// `->`
+    RightArrow,
+    /// `=>`
+    RightDoubleArrow,
+    /// `<-`
+    LeftArrow,
+    /// `<=`
+    LeftDoubleArrow,
+    /// `'` between letters, as in `it's`
+    Apostrophe,
+    /// `&copy;`, `&#169;` or `&#xa9;`, including `&` and `;`
+    CharacterReference(String),
+}
+
+impl Replacement {
+    /// The replacement as Unicode text. Character references are kept.
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Copyright => "\u{a9}",
+            Self::Registered => "\u{ae}",
+            Self::Trademark => "\u{2122}",
+            Self::EmDash { spaced: true } => "\u{2009}\u{2014}\u{2009}",
+            Self::EmDash { spaced: false } => "\u{2014}\u{200b}",
+            Self::Ellipsis => "\u{2026}",
+            Self::RightArrow => "\u{2192}",
+            Self::RightDoubleArrow => "\u{21d2}",
+            Self::LeftArrow => "\u{2190}",
+            Self::LeftDoubleArrow => "\u{21d0}",
+            Self::Apostrophe => "\u{2019}",
+            Self::CharacterReference(r) => r,
+        }
+    }
+}
+
+/// A replacement rule. The first group of the pattern is an optional backslash, the second
+/// is the replaced text. Text around the groups is context, and only checked.
+struct Rule {
+    re: Regex,
+    replacement: Replacement,
+}
+
+fn rule(re: &str, replacement: Replacement) -> Rule {
+    Rule {
+        re: Regex::new(re).unwrap(),
+        replacement,
+    }
+}
+
+lazy_static! {
+    /// Rules in the order they are applied
+    static ref RULES: Vec<Rule> = vec![
+        rule(r"(\\)?(\(C\))", Replacement::Copyright),
+        rule(r"(\\)?(\(R\))", Replacement::Registered),
+        rule(r"(\\)?(\(TM\))", Replacement::Trademark),
+        rule(r"(?m)(?:^| )(\\)?(--)(?: |$)", Replacement::EmDash { spaced: true }),
+        rule(r"\w(\\)?(--)\w", Replacement::EmDash { spaced: false }),
+        rule(r"(\\)?(\.\.\.)", Replacement::Ellipsis),
+        rule(r"\w(\\)?(')\w", Replacement::Apostrophe),
+        rule(r"(\\)?(->)", Replacement::RightArrow),
+        rule(r"(\\)?(=>)", Replacement::RightDoubleArrow),
+        rule(r"(\\)?(<-)", Replacement::LeftArrow),
+        rule(r"(\\)?(<=)", Replacement::LeftDoubleArrow),
+    ];
+}
+
+/// Substitute typographic replacements and character references in text nodes. Character
+/// references come first, so that they are kept as they are.
+pub fn substitute_replacements(mut nodes: Vec<Node>) -> Vec<Node> {
+    nodes = Node::substitute(nodes, &|s| character_references(s));
+    for rule in RULES.iter() {
+        nodes = Node::substitute(nodes, &|s| rule.apply(s));
+    }
+    nodes
+}
+
+fn replaced(replacement: Replacement) -> Node {
+    let inline = Inline::new(InlineKind::Replacement(replacement));
+    Node::Inline(inline, Vec::new())
+}
+
+impl Rule {
+    fn apply(&self, s: &str) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut rest = 0;
+        let mut from = 0;
+        while let Some(caps) = self.re.captures_at(s, from) {
+            let text = match (&self.replacement, caps.get(1)) {
+                // The spaces around an em dash are replaced too
+                (Replacement::EmDash { spaced: true }, None) => caps.get(0).unwrap(),
+                _ => caps.get(2).unwrap(),
+            };
+            let (start, node) = match caps.get(1) {
+                // The backslash is dropped
+                Some(escape) => (escape.start(), Node::Literal(text.as_str().to_owned())),
+                None => (text.start(), replaced(self.replacement.clone())),
+            };
+            if start > rest {
+                nodes.push(Node::Text(s[rest..start].to_owned()));
+            }
+            let source = s[start..text.end()].to_owned();
+            nodes.push(Node::Markup(source, Box::new(node)));
+            // Context after the text may be context of the next match too
+            rest = text.end();
+            from = text.end();
+        }
+        if rest < s.len() {
+            nodes.push(Node::Text(s[rest..].to_owned()));
+        }
+        nodes
+    }
+}
+
+fn character_references(s: &str) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"(\\)?&(?:[a-zA-Z][a-zA-Z]+\d{0,2}|#\d{2,6}|#x[\da-fA-F]{2,5});").unwrap();
+    }
+    replace_all(s, &RE, |caps| {
+        Some(vec![match caps.get(1) {
+            Some(_) => Node::Literal(caps[0][1..].to_owned()),
+            None => replaced(Replacement::CharacterReference(caps[0].to_owned())),
+        }])
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::subs::apply_with;
+
+    fn r(replacement: Replacement) -> Inline {
+        Inline::new(InlineKind::Replacement(replacement))
+    }
+
+    fn text(s: &str) -> Inline {
+        Inline::unquoted(s)
+    }
+
+    #[test]
+    fn symbols_and_arrows() {
+        assert_eq!(
+            apply_with("ACME(TM) (C) 2024 -> <= ...", substitute_replacements),
+            vec![
+                text("ACME"),
+                r(Replacement::Trademark),
+                text(" "),
+                r(Replacement::Copyright),
+                text(" 2024 "),
+                r(Replacement::RightArrow),
+                text(" "),
+                r(Replacement::LeftDoubleArrow),
+                text(" "),
+                r(Replacement::Ellipsis),
+            ]
+        );
+    }
+
+    #[test]
+    fn em_dashes() {
+        assert_eq!(
+            apply_with("a -- b and x--y--z", substitute_replacements),
+            vec![
+                text("a"),
+                r(Replacement::EmDash { spaced: true }),
+                text("b and x"),
+                r(Replacement::EmDash { spaced: false }),
+                text("y"),
+                r(Replacement::EmDash { spaced: false }),
+                text("z"),
+            ]
+        );
+        assert_eq!(
+            Inline::text(&apply_with("-- a -- b--c --", substitute_replacements)),
+            "\u{2009}\u{2014}\u{2009}a\u{2009}\u{2014}\u{2009}b\u{2014}\u{200b}c\u{2009}\u{2014}\u{2009}"
+        );
+        assert_eq!(
+            apply_with("a \\-- b", substitute_replacements),
+            vec![text("a -- b")]
+        );
+        // Not between words, nor surrounded by spaces
+        assert_eq!(
+            apply_with("--option, a-- b", substitute_replacements),
+            vec![text("--option, a-- b")]
+        );
+    }
+
+    #[test]
+    fn apostrophes() {
+        assert_eq!(
+            apply_with("it's rock'n'roll, 'quoted'", substitute_replacements),
+            vec![
+                text("it"),
+                r(Replacement::Apostrophe),
+                text("s rock"),
+                r(Replacement::Apostrophe),
+                text("n"),
+                r(Replacement::Apostrophe),
+                text("roll, 'quoted'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_and_references() {
+        assert_eq!(
+            apply_with("\\(C) a\\--b \\->", substitute_replacements),
+            vec![text("(C) a--b ->")]
+        );
+        assert_eq!(
+            apply_with("&copy;&#169;&#xa9; & (R)", substitute_replacements),
+            vec![
+                r(Replacement::CharacterReference("&copy;".to_owned())),
+                r(Replacement::CharacterReference("&#169;".to_owned())),
+                r(Replacement::CharacterReference("&#xa9;".to_owned())),
+                text(" & "),
+                r(Replacement::Registered),
+            ]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use super::{
+    anchors::Anchor,
+    block::{
+        Block, BlockContent, BlockContext, BlockStyle, CompoundBlock, SectionBlock, SimpleBlock,
+    },
+    diagnostic::Diagnostic,
+    document::Doctype,
+    inline::{Inline, InlineKind},
+    span::Span,
+    state::ParserState,
+};
+
+/// Style of a special section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionStyle {
+    Abstract,
+    Acknowledgments,
+    Appendix,
+    Bibliography,
+    Colophon,
+    Dedication,
+    Glossary,
+    Index,
+    Preface,
+}
+
+impl SectionStyle {
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "abstract" => Some(Self::Abstract),
+            "acknowledgments" => Some(Self::Acknowledgments),
+            "appendix" => Some(Self::Appendix),
+            "bibliography" => Some(Self::Bibliography),
+            "colophon" => Some(Self::Colophon),
+            "dedication" => Some(Self::Dedication),
+            "glossary" => Some(Self::Glossary),
+            "index" => Some(Self::Index),
+            "preface" => Some(Self::Preface),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Abstract => "abstract",
+            Self::Acknowledgments => "acknowledgments",
+            Self::Appendix => "appendix",
+            Self::Bibliography => "bibliography",
+            Self::Colophon => "colophon",
+            Self::Dedication => "dedication",
+            Self::Glossary => "glossary",
+            Self::Index => "index",
+            Self::Preface => "preface",
+        }
+    }
+    /// Whether a section of `level` may have this style in a document of `doctype`. Front and
+    /// back matter of books are top-level sections, as are appendices, abstracts of articles
+    /// and indexes. Bibliographies and glossaries may be nested, e.g. in an appendix.
+    pub fn is_allowed(&self, doctype: Doctype, level: usize) -> bool {
+        let book = doctype == Doctype::Book;
+        match self {
+            Self::Abstract => !book && level == 1,
+            Self::Preface | Self::Colophon | Self::Dedication | Self::Acknowledgments => {
+                book && level == 1
+            }
+            Self::Appendix | Self::Index => level == 1,
+            Self::Bibliography | Self::Glossary => level >= 1,
+        }
+    }
+    /// Whether a section of this style may have subsections. Abstracts, acknowledgments,
+    /// colophons, dedications and indexes may not.
+    pub fn allows_subsections(&self) -> bool {
+        !matches!(
+            self,
+            Self::Abstract
+                | Self::Acknowledgments
+                | Self::Colophon
+                | Self::Dedication
+                | Self::Index
+        )
+    }
+    /// Whether a section of this style may have blocks other than sections. The content of an
+    /// index is generated.
+    pub fn allows_blocks(&self) -> bool {
+        *self != Self::Index
+    }
+}
+
+/// Report blocks that special sections in `blocks` may not have, such as a subsection of a
+/// dedication. The blocks are kept.
+pub fn check_content(blocks: &mut [Block]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for block in blocks {
+        block.walk_mut(&mut |b| {
+            let (style, blocks) = match &b.context {
+                BlockContext::Section(SectionBlock {
+                    style: Some(style),
+                    blocks,
+                    ..
+                }) => (style, blocks),
+                _ => return,
+            };
+            for child in blocks {
+                let (kind, allowed) = match child.context {
+                    BlockContext::Section(_) => ("section", style.allows_subsections()),
+                    _ => ("block", style.allows_blocks()),
+                };
+                if !allowed {
+                    diagnostics.push(Diagnostic {
+                        span: child.span,
+                        message: format!("{} not allowed in {} section", kind, style.name()),
+                    });
+                }
+            }
+        });
+    }
+    diagnostics
+}
+
+/// Assigns section numbers as sections are parsed
+#[derive(Debug, Clone, Default)]
+pub struct SectionNumbering {
+    /// Number components of the current section and its ancestors, by level. `None` for
+    /// unnumbered sections.
+    path: Vec<Option<String>>,
+    /// Count of numbered sections at each level under the current parents
+    counters: Vec<usize>,
+    appendices: usize,
+}
+
+impl SectionNumbering {
+    /// Number of a section starting at `level`, e.g. `1.2` or `A.1`. Sections are numbered
+    /// when `sectnums` is set, up to `sectnumlevels`, and their parent is numbered. Special
+    /// sections are unnumbered, except appendices which are always lettered.
+    pub fn next(
+        &mut self,
+        level: usize,
+        style: Option<SectionStyle>,
+        state: &ParserState,
+    ) -> Option<String> {
+        self.path.resize(level, None);
+        self.counters.resize(level + 1, 0);
+        let max_level = state
+            .attrs
+            .get("sectnumlevels")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(3);
+        // Parts do not affect numbering
+        let parent_numbered = level <= 1 || matches!(self.path.last(), Some(Some(_)));
+        let component = match style {
+            Some(SectionStyle::Appendix) => {
+                self.appendices += 1;
+                let letter = (b'A' + ((self.appendices - 1) % 26) as u8) as char;
+                Some(letter.to_string())
+            }
+            Some(_) => None,
+            None if state.attrs.is_set("sectnums")
+                && parent_numbered
+                && (1..=max_level).contains(&level) =>
+            {
+                self.counters[level] += 1;
+                Some(self.counters[level].to_string())
+            }
+            None => None,
+        };
+        self.path.push(component.clone());
+        component?;
+        let number: Vec<&str> = self.path[1..]
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        Some(number.join("."))
+    }
+}
+
+/// Caption of a section, such as `Appendix A: `. Only appendices have one.
+pub fn caption(
+    style: Option<SectionStyle>,
+    number: Option<&str>,
+    state: &ParserState,
+) -> Option<String> {
+    match (style, number, state.attrs.get("appendix-caption")) {
+        (Some(SectionStyle::Appendix), Some(number), Some(label)) if !label.is_empty() => {
+            Some(format!("{} {}: ", label, number))
+        }
+        _ => None,
+    }
+}
+
+/// A part of a book. Blocks before its first section are its introduction, and are wrapped
+/// in an open block with the `partintro` style unless they already are one.
+pub fn part(mut section: SectionBlock) -> SectionBlock {
+    let intro_len = section
+        .blocks
+        .iter()
+        .position(|b| matches!(b.context, BlockContext::Section(_)))
+        .unwrap_or(section.blocks.len());
+    let is_intro = |b: &Block| b.style == BlockStyle::PartIntro;
+    match &section.blocks[..intro_len] {
+        [] => (),
+        [block] if is_intro(block) => (),
+        intro => {
+            let span = Span {
+                start: intro[0].span.start,
+                end: intro[intro.len() - 1].span.end,
+            };
+            let blocks: Vec<Block> = section.blocks.drain(..intro_len).collect();
+            let context = BlockContext::Open(BlockContent::Compound(CompoundBlock(blocks)));
+            let mut intro = Block::new(context, span);
+            intro.style = BlockStyle::PartIntro;
+            section.blocks.insert(0, intro);
+        }
+    }
+    section
+}
+
+/// Turn bibliography anchors, `[[[id]]]` and `[[[id, label]]]`, at the start of list items
+/// in a bibliography into item anchors. Unordered lists nested in other blocks, such as
+/// subsections, are included. The reference text is the label, or the ID, in brackets.
+pub fn bibliography(blocks: &mut [Block], state: &mut ParserState) {
+    for block in blocks {
+        block.walk_mut(&mut |b| {
+            if let BlockContext::UnorderedList(CompoundBlock(items)) = &mut b.context {
+                bibliography_items(items, state);
+            }
+        });
+    }
+}
+
+fn bibliography_items(items: &mut [Block], state: &mut ParserState) {
+    for item in items {
+        let text = match &mut item.context {
+            BlockContext::ListItem(CompoundBlock(blocks)) => match blocks.first_mut() {
+                Some(Block {
+                    context: BlockContext::Paragraph(SimpleBlock(inlines)),
+                    ..
+                }) => inlines,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let anchor = match text.first() {
+            Some(Inline {
+                kind:
+                    InlineKind::Anchor(
+                        anchor @ Anchor {
+                            bibliography: true, ..
+                        },
+                    ),
+                ..
+            }) => anchor.clone(),
+            _ => continue,
+        };
+        // Duplicate IDs are reported by the catalog
+        state.register_id(&anchor.id);
+        if let Some(reftext) = &anchor.reftext {
+            item.attrs
+                .get_or_insert_with(Default::default)
+                .insert("reftext", reftext);
+        }
+        item.anchor = Some(anchor.id);
+        text.remove(0);
+        if let Some(Inline {
+            kind: InlineKind::Unquoted(s),
+            ..
+        }) = text.first_mut()
+        {
+            *s = s.trim_start().to_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::document::Document;
+
+    fn sections(blocks: &[Block]) -> Vec<&SectionBlock> {
+        blocks
+            .iter()
+            .filter_map(|b| match &b.context {
+                BlockContext::Section(section) => Some(section),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn section_numbers() {
+        let doc = Document::parse(
+            ":sectnums:\n\n== One\n\n=== One One\n\n[preface]\n== Not in an article\n\n\
+             [glossary]\n== Glossary\n\n=== Term\n\n[appendix]\n== Extra\n\n=== Extra One\n\n\
+             [appendix]\n== More",
+        )
+        .unwrap();
+        let top = sections(&doc.blocks);
+        let numbers: Vec<_> = top.iter().map(|s| s.number.as_deref()).collect();
+        assert_eq!(
+            numbers,
+            vec![Some("1"), Some("2"), None, Some("A"), Some("B")]
+        );
+        assert_eq!(sections(&top[0].blocks)[0].number.as_deref(), Some("1.1"));
+        assert_eq!(top[1].style, None);
+        assert_eq!(top[2].style, Some(SectionStyle::Glossary));
+        assert_eq!(sections(&top[2].blocks)[0].number, None);
+        assert_eq!(sections(&top[3].blocks)[0].number.as_deref(), Some("A.1"));
+        assert_eq!(top[3].caption.as_deref(), Some("Appendix A: "));
+    }
+
+    #[test]
+    fn unnumbered_appendix_caption() {
+        let doc = Document::parse(":appendix-caption: Anhang\n\n[appendix]\n== Extra").unwrap();
+        let section = sections(&doc.blocks)[0];
+        assert_eq!(section.number.as_deref(), Some("A"));
+        assert_eq!(section.caption.as_deref(), Some("Anhang A: "));
+    }
+
+    #[test]
+    fn book_sections() {
+        let doc = Document::parse(
+            "= Book\n:doctype: book\n\n[preface]\n== Preface\n\n[abstract]\n== Not in a book\n\n\
+             [dedication]\n== Dedication",
+        )
+        .unwrap();
+        let styles: Vec<_> = sections(&doc.blocks).iter().map(|s| s.style).collect();
+        assert_eq!(
+            styles,
+            vec![
+                Some(SectionStyle::Preface),
+                None,
+                Some(SectionStyle::Dedication)
+            ]
+        );
+    }
+
+    #[test]
+    fn bibliography_entries() {
+        let doc = Document::parse(
+            "[bibliography]\n== References\n\n* [[[pp]]] Andy Hunt. The Pragmatic Programmer.\n\
+             * [[[gof, GoF]]] Design Patterns.\n* Not an entry.",
+        )
+        .unwrap();
+        let section = sections(&doc.blocks)[0];
+        let items = match &section.blocks[0].context {
+            BlockContext::UnorderedList(CompoundBlock(items)) => items,
+            other => panic!("expected a list, got {:?}", other),
+        };
+        assert_eq!(items[0].anchor.as_deref(), Some("pp"));
+        assert_eq!(
+            items[0].attrs.as_ref().unwrap().get("reftext"),
+            Some("[pp]")
+        );
+        assert_eq!(items[1].anchor.as_deref(), Some("gof"));
+        assert_eq!(
+            items[1].attrs.as_ref().unwrap().get("reftext"),
+            Some("[GoF]")
+        );
+        match &items[1].context {
+            BlockContext::ListItem(CompoundBlock(blocks)) => assert_eq!(
+                blocks[0].context,
+                BlockContext::Paragraph(SimpleBlock(vec![Inline::unquoted("Design Patterns.")]))
+            ),
+            _ => unreachable!(),
+        }
+        assert_eq!(items[2].anchor, None);
+    }
+
+    #[test]
+    fn nested_bibliography() {
+        let doc = Document::parse(
+            "[bibliography]\n== References\n\n=== Books\n\n* [[[pp]]] Pragmatic.\n\n\
+             ====\n* [[[gof]]] Patterns.\n====",
+        )
+        .unwrap();
+        assert_eq!(doc.catalog.refs["pp"].reftext.as_deref(), Some("[pp]"));
+        assert_eq!(doc.catalog.refs["gof"].reftext.as_deref(), Some("[gof]"));
+    }
+
+    #[test]
+    fn special_section_content() {
+        let doc = Document::parse(
+            "= Book\n:doctype: book\n\n[dedication]\n== For Ada\n\ntext\n\n=== Not here\n\n\
+             [index]\n== Index\n\nNot here either.\n\n[appendix]\n== Extra\n\n=== Fine",
+        )
+        .unwrap();
+        let messages: Vec<_> = doc.diagnostics.iter().map(|d| &d.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "section not allowed in dedication section",
+                "block not allowed in index section"
+            ]
+        );
+        assert_eq!(doc.diagnostics[0].span.start.line, 9);
+    }
+}
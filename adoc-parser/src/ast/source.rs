@@ -0,0 +1,176 @@
+use std::ops::RangeInclusive;
+
+use super::{block::VerbatimBlock, element::ElementAttrs, state::ParserState, subs::Substitutions};
+
+/// A listing block with the `source` style, or a fenced code block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceBlock {
+    /// From the second positional attribute, the `language` attribute, the fence, or the
+    /// `source-language` document attribute
+    pub language: Option<String>,
+    pub linenums: bool,
+    /// Number of the first line
+    pub start: usize,
+    /// Lines to highlight, numbered from `start`
+    pub highlight: Vec<RangeInclusive<usize>>,
+    /// Indentation the lines were re-indented to
+    pub indent: Option<usize>,
+    pub content: VerbatimBlock,
+}
+
+impl SourceBlock {
+    /// `language` is the language of a fenced code block, which takes precedence over
+    /// attributes. Invalid numbers in attributes are ignored.
+    pub fn new(
+        lines: &[String],
+        language: Option<String>,
+        attrs: Option<&ElementAttrs>,
+        state: &ParserState,
+    ) -> Self {
+        let get = |name| attrs.and_then(|a| a.get(name));
+        let language = language
+            .or_else(|| get("language").map(str::to_owned))
+            .or_else(|| {
+                attrs
+                    .and_then(|a| a.positional(1))
+                    .filter(|l| !l.is_empty())
+                    .map(str::to_owned)
+            })
+            .or_else(|| state.attrs.get("source-language").map(str::to_owned));
+        let linenums = attrs
+            .map(|a| a.positional(2) == Some("linenums") || a.has_option("linenums"))
+            .unwrap_or(false);
+        let start = get("start").and_then(|s| s.parse().ok()).unwrap_or(1);
+        let highlight = get("highlight").map(parse_highlight).unwrap_or_default();
+        let indent = get("indent").and_then(|s| s.parse().ok());
+        let subs = Substitutions::for_block(Substitutions::verbatim(), attrs);
+        let content = match indent {
+            Some(indent) => VerbatimBlock::new(&reindent(lines, indent), &subs, state),
+            None => VerbatimBlock::new(lines, &subs, state),
+        };
+        Self {
+            language,
+            linenums,
+            start,
+            highlight,
+            indent,
+            content,
+        }
+    }
+}
+
+/// Line ranges like `2..4,7` or `2-4;7`
+fn parse_highlight(s: &str) -> Vec<RangeInclusive<usize>> {
+    s.split([',', ';'])
+        .map(str::trim)
+        .filter_map(|range| {
+            let (start, end) = match range.split_once("..").or_else(|| range.split_once('-')) {
+                Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+                None => {
+                    let line = range.parse().ok()?;
+                    (line, line)
+                }
+            };
+            Some(start..=end)
+        })
+        .collect()
+}
+
+/// Replace the indentation common to all non-empty lines by `indent` spaces
+fn reindent(lines: &[String], indent: usize) -> Vec<String> {
+    let prefix = " ".repeat(indent);
+    super::block::dedent(lines)
+        .into_iter()
+        .map(|l| match l.is_empty() {
+            true => l,
+            false => format!("{}{}", prefix, l),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{
+        block::{Block, BlockContext},
+        document::Document,
+        inline::Inline,
+    };
+
+    fn sources(s: &str) -> Vec<SourceBlock> {
+        Document::parse(s)
+            .unwrap()
+            .blocks
+            .into_iter()
+            .map(|b: Block| match b.context {
+                BlockContext::Source(source) => source,
+                other => panic!("expected a source block, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn highlight_ranges() {
+        assert_eq!(parse_highlight("2..4,7"), vec![2..=4, 7..=7]);
+        assert_eq!(parse_highlight("1-2; 5"), vec![1..=2, 5..=5]);
+        assert_eq!(parse_highlight("x,3"), vec![3..=3]);
+    }
+
+    #[test]
+    fn source_attributes() {
+        let s = sources(
+            "[source,rust,linenums,highlight=2..3,start=10]\n----\nfn main() {\n}\n----\n\n\
+             [source%linenums,indent=2]\n----\n        a\n          b\n----",
+        );
+        assert_eq!(s[0].language.as_deref(), Some("rust"));
+        assert!(s[0].linenums);
+        assert_eq!(s[0].start, 10);
+        assert_eq!(s[0].highlight, vec![2..=3]);
+        assert_eq!(s[1].language, None);
+        assert!(s[1].linenums);
+        assert_eq!(s[1].indent, Some(2));
+        assert_eq!(
+            s[1].content,
+            VerbatimBlock(vec![Inline::unquoted("  a\n    b")])
+        );
+    }
+
+    #[test]
+    fn source_language_default() {
+        let s = sources(
+            ":source-language: ruby\n\n[source]\n----\nputs 1\n----\n\n----\nputs 2\n----\n\n\
+             [source,python]\nprint(3)",
+        );
+        assert_eq!(s[0].language.as_deref(), Some("ruby"));
+        assert_eq!(s[1].language.as_deref(), Some("ruby"));
+        assert_eq!(s[2].language.as_deref(), Some("python"));
+    }
+
+    #[test]
+    fn fenced_code() {
+        let s = sources("```rust\nlet x = 1;\n\nlet y = 2;\n```\n\n```\nplain\n```");
+        assert_eq!(s[0].language.as_deref(), Some("rust"));
+        assert_eq!(
+            s[0].content,
+            VerbatimBlock(vec![Inline::unquoted("let x = 1;\n\nlet y = 2;")])
+        );
+        assert_eq!(s[1].language, None);
+    }
+
+    #[test]
+    fn fenced_code_attributes() {
+        let s = sources(
+            "[source,python,linenums,highlight=1,start=5]\n```rust\nlet x = 1;\n```\n\n\
+             [source,python,indent=0]\n```\n  print(1)\n```",
+        );
+        assert_eq!(s[0].language.as_deref(), Some("rust"));
+        assert!(s[0].linenums);
+        assert_eq!(s[0].start, 5);
+        assert_eq!(s[0].highlight, vec![1..=1]);
+        assert_eq!(s[1].language.as_deref(), Some("python"));
+        assert_eq!(
+            s[1].content,
+            VerbatimBlock(vec![Inline::unquoted("print(1)")])
+        );
+    }
+}
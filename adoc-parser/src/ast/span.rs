@@ -0,0 +1,40 @@
+/// A position in the source file. Lines and columns are 1-based, columns count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self { line: 1, column: 1 }
+    }
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+    /// The position `line` lines and `column` characters further. The column is relative
+    /// to this position on its own line, and to the start of the line otherwise.
+    pub fn offset(self, line: usize, column: usize) -> Self {
+        match line {
+            0 => Self::new(self.line, self.column + column),
+            _ => Self::new(self.line + line, 1 + column),
+        }
+    }
+    /// The position right after `text`, if it starts here
+    pub fn advance(self, text: &str) -> Self {
+        text.chars().fold(self, |pos, c| match c {
+            '\n' => Self::new(pos.line + 1, 1),
+            _ => Self::new(pos.line, pos.column + 1),
+        })
+    }
+}
+
+/// The source range of an element. `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{document::DocumentAttrs, extensions::Extensions, section::SectionNumbering};
+
+/// State shared by the block parsers of one document
+#[derive(Debug, Clone, Default)]
+pub struct ParserState {
+    pub attrs: DocumentAttrs,
+    /// IDs registered so far, including those of nested documents
+    pub ids: HashSet<String>,
+    pub numbering: SectionNumbering,
+    pub extensions: Extensions,
+}
+
+impl ParserState {
+    /// State for a nested document, such as an AsciiDoc table cell. It starts with the
+    /// attributes and IDs of this document, so attribute entries in the nested document
+    /// stay local to it. Its sections are numbered separately.
+    pub fn nested(&self) -> Self {
+        Self {
+            attrs: self.attrs.clone(),
+            ids: self.ids.clone(),
+            numbering: SectionNumbering::default(),
+            extensions: self.extensions.clone(),
+        }
+    }
+    /// Take back the IDs registered by a nested document
+    pub fn join(&mut self, nested: ParserState) {
+        self.ids.extend(nested.ids);
+    }
+    /// Register an ID. Returns false if it is already taken.
+    pub fn register_id(&mut self, id: &str) -> bool {
+        self.ids.insert(id.to_owned())
+    }
+    /// Generate and register a unique ID from a title, following the `idprefix` and
+    /// `idseparator` attributes.
+    pub fn generate_id(&mut self, title: &str) -> String {
+        lazy_static! {
+            // Tags, character references, and anything else that is not a word character
+            static ref RE_INVALID: Regex =
+                Regex::new(r"<[^>]+>|&(?:[a-z][a-z]+\d{0,2}|#\d{2,5}|#x[\da-f]{2,4});|[^ \w\-.]+")
+                    .unwrap();
+            static ref RE_SEP: Regex = Regex::new(r"[ .\-]+").unwrap();
+        }
+        let prefix = self.attrs.get("idprefix").unwrap_or("").to_owned();
+        let separator = self.attrs.get("idseparator").unwrap_or("").to_owned();
+        let id = title.to_lowercase();
+        let id = RE_INVALID.replace_all(&id, "");
+        let id = RE_SEP.replace_all(&id, separator.as_str());
+        let id = format!("{}{}", prefix, id.trim_matches(|c| separator.contains(c)));
+        let mut unique = id.clone();
+        let mut n = 2;
+        while !self.register_id(&unique) {
+            unique = format!("{}{}{}", id, separator, n);
+            n += 1;
+        }
+        unique
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generated_ids() {
+        let mut state = ParserState::default();
+        assert_eq!(state.generate_id("Getting Started"), "_getting_started");
+        assert_eq!(state.generate_id("Getting Started"), "_getting_started_2");
+        assert_eq!(
+            state.generate_id("What's <em>new</em> in 1.2?"),
+            "_whats_new_in_1_2"
+        );
+        state.attrs.set("idprefix", "");
+        state.attrs.set("idseparator", "-");
+        assert_eq!(state.generate_id("Getting Started"), "getting-started");
+    }
+
+    #[test]
+    fn nested_state_shares_ids() {
+        let mut state = ParserState::default();
+        state.generate_id("Intro");
+        let mut nested = state.nested();
+        assert_eq!(nested.generate_id("Intro"), "_intro_2");
+        nested.attrs.set("local", "");
+        state.join(nested);
+        assert!(state.ids.contains("_intro_2"));
+        assert!(!state.attrs.is_set("local"));
+    }
+}
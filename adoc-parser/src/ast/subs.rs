@@ -0,0 +1,654 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    breaks::substitute_line_breaks,
+    callouts::substitute_callouts,
+    element::ElementAttrs,
+    inline::{Inline, InlineKind},
+    macros::substitute_macros,
+    passthroughs::{extract_passthroughs, raw},
+    quotes::substitute_quotes,
+    replacements::substitute_replacements,
+    state::ParserState,
+};
+
+/// A substitution applied to the text of a block, in the order of this enum by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Substitution {
+    /// `<`, `>` and `&`. Text is escaped when rendered, so without this substitution, text
+    /// with these characters is kept in a passthrough.
+    SpecialCharacters,
+    Quotes,
+    /// Attribute references, `{name}`
+    Attributes,
+    Replacements,
+    Macros,
+    PostReplacements,
+    /// Callout numbers in verbatim blocks
+    Callouts,
+}
+
+impl Substitution {
+    /// A substitution by name, or by the abbreviation used in `pass:[]` macros
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "specialcharacters" | "specialchars" | "c" => Some(Self::SpecialCharacters),
+            "quotes" | "q" => Some(Self::Quotes),
+            "attributes" | "a" => Some(Self::Attributes),
+            "replacements" | "r" => Some(Self::Replacements),
+            "macros" | "m" => Some(Self::Macros),
+            "post_replacements" | "p" => Some(Self::PostReplacements),
+            "callouts" => Some(Self::Callouts),
+            _ => None,
+        }
+    }
+}
+
+/// Substitutions in the order they are applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitutions(pub Vec<Substitution>);
+
+impl Substitutions {
+    /// No substitutions, for passthrough content
+    pub fn none() -> Self {
+        Self(Vec::new())
+    }
+    /// All substitutions but callouts, for simple content such as paragraphs
+    pub fn normal() -> Self {
+        use Substitution::*;
+        Self(vec![
+            SpecialCharacters,
+            Quotes,
+            Attributes,
+            Replacements,
+            Macros,
+            PostReplacements,
+        ])
+    }
+    /// For verbatim content, such as listings
+    pub fn verbatim() -> Self {
+        Self(vec![
+            Substitution::SpecialCharacters,
+            Substitution::Callouts,
+        ])
+    }
+    /// A preset by name
+    fn preset(s: &str) -> Option<Self> {
+        match s {
+            "none" => Some(Self::none()),
+            "normal" => Some(Self::normal()),
+            "verbatim" => Some(Self::verbatim()),
+            _ => None,
+        }
+    }
+
+    /// Substitutions from a `subs` attribute such as `quotes,macros`, `verbatim` or
+    /// `+quotes,-callouts`. Names with a modifier, `+name` (append), `name+` (prepend) or
+    /// `-name` (remove), change `default`; otherwise the list replaces it. Unknown names
+    /// are ignored.
+    pub fn parse(spec: &str, default: &Substitutions) -> Self {
+        let names: Vec<&str> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .collect();
+        let incremental = names
+            .first()
+            .map(|n| n.starts_with(['+', '-']) || n.ends_with('+'))
+            .unwrap_or(false);
+        let mut subs = match incremental {
+            true => default.0.clone(),
+            false => Vec::new(),
+        };
+        let expand = |name: &str| {
+            Self::preset(name)
+                .map(|p| p.0)
+                .or_else(|| Substitution::from_name(name).map(|s| vec![s]))
+                .unwrap_or_default()
+        };
+        for name in names {
+            if let Some(name) = name.strip_prefix('+') {
+                subs.extend(expand(name));
+            } else if let Some(name) = name.strip_suffix('+') {
+                subs.splice(0..0, expand(name));
+            } else if let Some(name) = name.strip_prefix('-') {
+                let removed = expand(name);
+                subs.retain(|s| !removed.contains(s));
+            } else {
+                subs.extend(expand(name));
+            }
+        }
+        let mut unique = Vec::new();
+        for sub in subs {
+            if !unique.contains(&sub) {
+                unique.push(sub);
+            }
+        }
+        Self(unique)
+    }
+
+    /// Substitutions of a block with the `subs` attribute in `attrs`, or else `default`,
+    /// which depends on the content model
+    pub fn for_block(default: Self, attrs: Option<&ElementAttrs>) -> Self {
+        match attrs.and_then(|a| a.get("subs")) {
+            Some(spec) => Self::parse(spec, &default),
+            None => default,
+        }
+    }
+
+    pub fn contains(&self, sub: Substitution) -> bool {
+        self.0.contains(&sub)
+    }
+
+    /// Apply the substitutions to `text`
+    pub fn apply(&self, text: &str, state: &ParserState) -> Vec<Inline> {
+        let mut nodes = self.substitute(vec![Node::Text(text.to_owned())], state);
+        if !self.contains(Substitution::SpecialCharacters) {
+            nodes = unescaped(nodes);
+        }
+        Node::into_inlines(nodes)
+    }
+
+    /// Apply the substitutions to nodes. With macros, passthroughs are extracted first.
+    pub fn substitute(&self, mut nodes: Vec<Node>, state: &ParserState) -> Vec<Node> {
+        if self.contains(Substitution::Macros) {
+            nodes = extract_passthroughs(nodes, state);
+        }
+        for sub in &self.0 {
+            nodes = match sub {
+                Substitution::Quotes => substitute_quotes(nodes),
+                Substitution::Attributes => {
+                    Node::substitute(nodes, &|s| substitute_attributes(s, state))
+                }
+                Substitution::Replacements => substitute_replacements(nodes),
+                Substitution::Macros => substitute_macros(nodes, state),
+                Substitution::PostReplacements => substitute_line_breaks(nodes),
+                Substitution::Callouts => substitute_callouts(nodes),
+                // Applied when rendered
+                Substitution::SpecialCharacters => nodes,
+            };
+        }
+        nodes
+    }
+
+    /// Apply the substitutions to raw content, such as a passthrough block. Raw content has
+    /// no inline elements, so only attribute references are replaced.
+    pub fn apply_raw(&self, text: &str, state: &ParserState) -> String {
+        if !self.contains(Substitution::Attributes) {
+            return text.to_owned();
+        }
+        Node::substitute(vec![Node::Text(text.to_owned())], &|s| {
+            substitute_attributes(s, state)
+        })
+        .into_iter()
+        .map(|node| match node.unmarked() {
+            Node::Text(s) | Node::Literal(s) => s,
+            Node::Inline(..) | Node::Markup(..) => unreachable!(),
+        })
+        .collect()
+    }
+}
+
+/// Put text with special characters in passthroughs, so that they are not escaped
+fn unescaped(nodes: Vec<Node>) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .map(|node| match node.unmarked() {
+            Node::Text(s) | Node::Literal(s) if s.contains(['<', '>', '&']) => {
+                raw(vec![Node::Literal(s)])
+            }
+            Node::Inline(inline, content) if !matches!(inline.kind, InlineKind::Passthrough(_)) => {
+                Node::Inline(inline, unescaped(content))
+            }
+            node => node,
+        })
+        .collect()
+}
+
+/// Replace references to attributes which are set by their value. A reference is kept when
+/// the attribute is not set, or when escaped, `\{name}`.
+fn substitute_attributes(s: &str, state: &ParserState) -> Vec<Node> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(\\)?\{([\w][\w\-]*)\}").unwrap();
+    }
+    replace_all(s, &RE, |caps| {
+        match (caps.get(1), state.attrs.get(&caps[2])) {
+            (Some(_), _) => Some(vec![Node::Literal(caps[0][1..].to_owned())]),
+            (None, Some(value)) => Some(vec![Node::Text(value.to_owned())]),
+            (None, None) => None,
+        }
+    })
+}
+
+/// Replace each match of `re` in `s` by the nodes `f` returns. Matches for which `f` returns
+/// `None` are kept as text. A match replaced by a single node is its markup.
+pub fn replace_all<F>(s: &str, re: &Regex, f: F) -> Vec<Node>
+where
+    F: Fn(&Captures) -> Option<Vec<Node>>,
+{
+    let mut nodes = Vec::new();
+    let mut rest = 0;
+    for caps in re.captures_iter(s) {
+        let whole = caps.get(0).unwrap();
+        if let Some(mut replaced) = f(&caps) {
+            if whole.start() > rest {
+                nodes.push(Node::Text(s[rest..whole.start()].to_owned()));
+            }
+            match &replaced[..] {
+                [Node::Literal(_) | Node::Inline(..)] => {
+                    let node = Box::new(replaced.remove(0));
+                    nodes.push(Node::Markup(whole.as_str().to_owned(), node));
+                }
+                _ => nodes.extend(replaced),
+            }
+            rest = whole.end();
+        }
+    }
+    if rest < s.len() {
+        nodes.push(Node::Text(s[rest..].to_owned()));
+    }
+    nodes
+}
+
+/// Inline content while substitutions are applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// Text open to later substitutions
+    Text(String),
+    /// Text no substitution applies to, such as escaped markup
+    Literal(String),
+    /// An inline element. Its content is kept apart until substitutions are done.
+    Inline(Inline, Vec<Node>),
+    /// A literal or an inline made of markup, with the source text of the markup
+    Markup(String, Box<Node>),
+}
+
+impl Node {
+    /// Apply `f` to the text of `nodes` at each level of nesting. Other nodes are replaced by
+    /// placeholder characters, so that `f` can match markup around them, e.g. quotes around
+    /// quoted text, and restored afterwards. Private use characters of the text are stashed
+    /// too, so that they are not mistaken for placeholders.
+    pub fn substitute<F>(nodes: Vec<Node>, f: &F) -> Vec<Node>
+    where
+        F: Fn(&Stashed) -> Vec<Node>,
+    {
+        let mut stash = Vec::new();
+        let mut text = String::new();
+        for node in nodes {
+            match node {
+                Node::Text(s) => {
+                    for c in s.chars() {
+                        if stashed(c).is_some() {
+                            text.push(placeholder(stash.len()));
+                            stash.push(Node::Literal(c.to_string()));
+                        } else {
+                            text.push(c);
+                        }
+                    }
+                }
+                node => {
+                    text.push(placeholder(stash.len()));
+                    stash.push(node.substitute_content(f));
+                }
+            }
+        }
+        let nodes = match text.is_empty() {
+            true => Vec::new(),
+            false => f(&Stashed {
+                text: &text,
+                stash: &stash,
+            }),
+        };
+        // The markup of new nodes may contain placeholders too
+        let nodes = nodes
+            .into_iter()
+            .map(|node| match node {
+                Node::Markup(source, node) => {
+                    Node::Markup(expand(&source, &stash, Node::source), node)
+                }
+                node => node,
+            })
+            .collect();
+        Self::restore(nodes, &mut stash)
+    }
+
+    /// Apply `f` to the content of an inline
+    fn substitute_content<F>(self, f: &F) -> Node
+    where
+        F: Fn(&Stashed) -> Vec<Node>,
+    {
+        match self {
+            Node::Inline(inline, content) => Node::Inline(inline, Self::substitute(content, f)),
+            Node::Markup(source, node) => {
+                Node::Markup(source, Box::new(node.substitute_content(f)))
+            }
+            node => node,
+        }
+    }
+
+    /// Replace placeholders in text by the stashed nodes
+    fn restore(nodes: Vec<Node>, stash: &mut [Node]) -> Vec<Node> {
+        let mut restored = Vec::new();
+        for node in nodes {
+            match node {
+                Node::Text(s) => Self::restore_text(&s, Node::Text, stash, &mut restored),
+                Node::Literal(s) => Self::restore_text(&s, Node::Literal, stash, &mut restored),
+                Node::Inline(inline, content) => {
+                    restored.push(Node::Inline(inline, Self::restore(content, stash)))
+                }
+                Node::Markup(source, node) => {
+                    let mut nodes = Self::restore(vec![*node], stash);
+                    match nodes.len() {
+                        1 => restored.push(Node::Markup(source, Box::new(nodes.remove(0)))),
+                        _ => restored.extend(nodes),
+                    }
+                }
+            }
+        }
+        restored
+    }
+
+    /// Split text at its placeholders, which escaped markup may contain too
+    fn restore_text(
+        s: &str,
+        wrap: fn(String) -> Node,
+        stash: &mut [Node],
+        restored: &mut Vec<Node>,
+    ) {
+        let mut rest = s;
+        while let Some((i, c)) = rest
+            .char_indices()
+            .find(|(_, c)| stashed(*c).map(|i| i < stash.len()).unwrap_or(false))
+        {
+            if i > 0 {
+                restored.push(wrap(rest[..i].to_owned()));
+            }
+            let index = stashed(c).unwrap();
+            let node = std::mem::replace(&mut stash[index], Node::Literal(String::new()));
+            restored.push(node);
+            rest = &rest[i + c.len_utf8()..];
+        }
+        if !rest.is_empty() {
+            restored.push(wrap(rest.to_owned()));
+        }
+    }
+
+    /// The node without the markup it was made of
+    pub fn unmarked(self) -> Node {
+        match self {
+            Node::Markup(_, node) => node.unmarked(),
+            node => node,
+        }
+    }
+
+    /// The markup this node was made of, or its text
+    fn source(&self) -> String {
+        match self {
+            Node::Markup(source, _) => source.clone(),
+            node => node.text(),
+        }
+    }
+
+    /// Plain text of the node, without markup
+    fn text(&self) -> String {
+        match self {
+            Node::Text(s) | Node::Literal(s) => s.clone(),
+            Node::Inline(..) => Inline::text(&Self::into_inlines(vec![self.clone()])),
+            Node::Markup(_, node) => node.text(),
+        }
+    }
+
+    /// The final inlines. Adjacent text is merged.
+    pub fn into_inlines(nodes: Vec<Node>) -> Vec<Inline> {
+        let mut inlines = Vec::new();
+        let mut text = String::new();
+        for node in nodes {
+            match node.unmarked() {
+                Node::Text(s) | Node::Literal(s) => text.push_str(&s),
+                Node::Markup(..) => unreachable!(),
+                Node::Inline(mut inline, content) => {
+                    if !text.is_empty() {
+                        inlines.push(Inline::unquoted(&std::mem::take(&mut text)));
+                    }
+                    if let Some(c) = inline.kind.content_mut() {
+                        *c = Self::into_inlines(content);
+                    }
+                    inlines.push(inline);
+                }
+            }
+        }
+        if !text.is_empty() {
+            inlines.push(Inline::unquoted(&text));
+        }
+        inlines
+    }
+}
+
+/// A finished inline. Its text is not open to substitutions.
+impl From<Inline> for Node {
+    fn from(mut inline: Inline) -> Self {
+        if let InlineKind::Unquoted(s) = inline.kind {
+            return Node::Literal(s);
+        }
+        let content = match inline.kind.content_mut() {
+            Some(content) => std::mem::take(content)
+                .into_iter()
+                .map(Node::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        Node::Inline(inline, content)
+    }
+}
+
+/// The inlines `substitute` makes of `s`, to test a single substitution
+#[cfg(test)]
+pub fn apply_with<F>(s: &str, substitute: F) -> Vec<Inline>
+where
+    F: FnOnce(Vec<Node>) -> Vec<Node>,
+{
+    Node::into_inlines(substitute(vec![Node::Text(s.to_owned())]))
+}
+
+/// The text a substitution applies to, where placeholders stand for the other nodes
+pub struct Stashed<'a> {
+    text: &'a str,
+    stash: &'a [Node],
+}
+
+impl Stashed<'_> {
+    /// `s`, a part of the text, with its placeholders replaced by the markup of their nodes.
+    /// For targets and IDs, which are taken as they are written.
+    pub fn source(&self, s: &str) -> String {
+        expand(s, self.stash, Node::source)
+    }
+    /// `s`, a part of the text, with its placeholders replaced by the text of their nodes
+    pub fn text(&self, s: &str) -> String {
+        expand(s, self.stash, Node::text)
+    }
+}
+
+impl std::ops::Deref for Stashed<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.text
+    }
+}
+
+/// Replace the placeholders in `s` by `f` of their nodes
+fn expand(s: &str, stash: &[Node], f: fn(&Node) -> String) -> String {
+    s.chars()
+        .map(|c| match stashed(c).and_then(|i| stash.get(i)) {
+            Some(node) => f(node),
+            None => c.to_string(),
+        })
+        .collect()
+}
+
+/// Placeholders are characters of a private use area
+const PLACEHOLDER_BASE: u32 = 0xF0000;
+
+fn placeholder(index: usize) -> char {
+    char::from_u32(PLACEHOLDER_BASE + index as u32).unwrap()
+}
+
+fn stashed(c: char) -> Option<usize> {
+    (c as u32).checked_sub(PLACEHOLDER_BASE).map(|i| i as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{inline::QuoteType, passthroughs::Passthrough, replacements::Replacement};
+    use Substitution::*;
+
+    #[test]
+    fn subs_attribute() {
+        let normal = Substitutions::normal();
+        assert_eq!(
+            Substitutions::parse("quotes,macros", &normal),
+            Substitutions(vec![Quotes, Macros])
+        );
+        assert_eq!(
+            Substitutions::parse("+quotes,-attributes", &Substitutions::verbatim()),
+            Substitutions(vec![SpecialCharacters, Callouts, Quotes])
+        );
+        assert_eq!(
+            Substitutions::parse("attributes+", &Substitutions::verbatim()),
+            Substitutions(vec![Attributes, SpecialCharacters, Callouts])
+        );
+        assert_eq!(
+            Substitutions::parse("verbatim", &normal),
+            Substitutions::verbatim()
+        );
+        assert_eq!(Substitutions::parse("none", &normal), Substitutions::none());
+        assert_eq!(
+            Substitutions::parse("-normal,+q", &normal),
+            Substitutions(vec![Quotes])
+        );
+    }
+
+    #[test]
+    fn attribute_references() {
+        let mut state = ParserState::default();
+        state.attrs.set("product", "AsciiDoc");
+        let subs = Substitutions(vec![Attributes]);
+        assert_eq!(
+            subs.apply("{product} {missing} \\{product}", &state),
+            vec![Inline::unquoted("AsciiDoc {missing} {product}")]
+        );
+    }
+
+    #[test]
+    fn special_characters() {
+        let state = ParserState::default();
+        let text = "<b>*bold*</b> & more";
+        assert_eq!(
+            Substitutions::normal().apply(text, &state)[0],
+            Inline::unquoted("<b>")
+        );
+        let raw = |s: &str| {
+            Inline::new(InlineKind::Passthrough(Passthrough {
+                subs: Substitutions::none(),
+                content: vec![Inline::unquoted(s)],
+            }))
+        };
+        assert_eq!(
+            Substitutions(vec![Quotes]).apply(text, &state),
+            vec![
+                raw("<b>"),
+                Inline::new(InlineKind::Quoted {
+                    ty: QuoteType::Strong,
+                    content: vec![Inline::unquoted("bold")]
+                }),
+                raw("</b> & more"),
+            ]
+        );
+    }
+
+    /// Escaped markup, as in Asciidoctor: each row is the source and the text it renders to
+    const ESCAPES: &[(&str, &str)] = &[
+        // Quotes; unconstrained marks take two backslashes
+        (r"\*not strong*", "*not strong*"),
+        (r"\_not emphasis_", "_not emphasis_"),
+        (r"\`not monospaced`", "`not monospaced`"),
+        (r"\#not marked#", "#not marked#"),
+        (r"E=mc\^2^", "E=mc^2^"),
+        (r"H\~2~O", "H~2~O"),
+        (r"\\**not strong**", "**not strong**"),
+        (r"\\__func__", "__func__"),
+        (r"\\``not monospaced``", "``not monospaced``"),
+        (r"\\##not marked##", "##not marked##"),
+        // Attribute references
+        (r"\{product}", "{product}"),
+        // Replacements
+        (r"\(C) \(R) \(TM)", "(C) (R) (TM)"),
+        (r"a \-- b", "a -- b"),
+        (r"wait\...", "wait..."),
+        (r"\-> \=> \<- \<=", "-> => <- <="),
+        (r"\&#169;", "&#169;"),
+        // Passthroughs
+        (r"\+{product}+", "+AsciiDoc+"),
+        (r"\+++<b>+++", "+++<b>+++"),
+        (r"\pass:[<b>]", "pass:[<b>]"),
+        (r"\stem:[x^2]", "stem:[x^2]"),
+        // Macros
+        (r"\<<install>>", "<<install>>"),
+        (r"\xref:install[]", "xref:install[]"),
+        (r"\https://example.org", "https://example.org"),
+        (r"\<https://example.org>", "<https://example.org>"),
+        (r"\link:index.html[Home]", "link:index.html[Home]"),
+        (r"\doc@example.org", "doc@example.org"),
+        (r"\footnote:[A note.]", "footnote:[A note.]"),
+        (r"\image:logo.png[Logo]", "image:logo.png[Logo]"),
+        (r"\icon:heart[]", "icon:heart[]"),
+        (r"\[[anchor]]", "[[anchor]]"),
+        (r"\anchor:anchor[]", "anchor:anchor[]"),
+        (r"\((term))", "((term))"),
+        (r"\indexterm:[term]", "indexterm:[term]"),
+        (r"\kbd:[F11]", "kbd:[F11]"),
+    ];
+
+    #[test]
+    fn escapes() {
+        let mut state = ParserState::default();
+        state.attrs.set("product", "AsciiDoc");
+        state.attrs.set("experimental", "");
+        for (source, text) in ESCAPES {
+            assert_eq!(
+                Substitutions::normal().apply(source, &state),
+                vec![Inline::unquoted(text)],
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn placeholders() {
+        let state = ParserState::default();
+        assert_eq!(
+            Substitutions::normal().apply("Private \u{F0000} and \u{F0001}chars...", &state),
+            vec![
+                Inline::unquoted("Private \u{F0000} and \u{F0001}chars"),
+                Inline::new(InlineKind::Replacement(Replacement::Ellipsis)),
+            ]
+        );
+        assert_eq!(
+            Substitutions::normal().apply("\\footnote:[a...]", &state),
+            vec![
+                Inline::unquoted("footnote:[a"),
+                Inline::new(InlineKind::Replacement(Replacement::Ellipsis)),
+                Inline::unquoted("]"),
+            ]
+        );
+        let nodes = substitute_replacements(vec![Node::Text("it's \\-- \u{F0000}...".to_owned())]);
+        Node::substitute(nodes, &|s| {
+            assert_eq!(s.source(s), "it's \\-- \u{F0000}...");
+            assert_eq!(s.text(s), "it\u{2019}s -- \u{F0000}\u{2026}");
+            Vec::new()
+        });
+    }
+}
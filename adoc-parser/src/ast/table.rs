@@ -0,0 +1,442 @@
+use crate::ast::reader::{
+    split_cells, CellSpec, CellStyle, ColumnSpec, ParserError, RawCell, TableFormat,
+};
+
+use super::{
+    block::{
+        Block, BlockContent, BlockContext, CompoundBlock, QuoteBlock, SectionScope, SimpleBlock,
+        VerbatimBlock,
+    },
+    element::ElementAttrs,
+    span::{Position, Span},
+    state::ParserState,
+    subs::Substitutions,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableBlock {
+    pub format: TableFormat,
+    pub separator: char,
+    pub columns: Vec<ColumnSpec>,
+    pub header: Option<TableRow>,
+    pub body: Vec<TableRow>,
+    pub footer: Option<TableRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableRow(pub Vec<TableCell>);
+
+/// A cell. The block has a `TableCell` context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCell {
+    pub spec: CellSpec,
+    pub block: Block,
+}
+
+impl TableBlock {
+    /// Parse the lines between table delimiters, which start at `origin`. `delimiter` is the
+    /// first character of the delimiter, which selects the default format and separator.
+    pub fn parse(
+        lines: &[String],
+        origin: Position,
+        delimiter: char,
+        attrs: &ElementAttrs,
+        state: &mut ParserState,
+    ) -> Result<Self, ParserError> {
+        let format = match attrs.get("format") {
+            Some(f) => TableFormat::from_name(f)
+                .ok_or_else(|| ParserError::Fail(format!("unknown table format {}", f)))?,
+            None => TableFormat::from_delimiter(delimiter),
+        };
+        let separator = match attrs.get("separator") {
+            Some("\\t") => '\t',
+            Some(s) => s
+                .chars()
+                .next()
+                .ok_or_else(|| ParserError::fail("empty table separator"))?,
+            None => format.default_separator(delimiter),
+        };
+        let groups = split_cells(lines, format, separator);
+        let first_row_width = groups
+            .first()
+            .map(|(_, cells)| {
+                cells
+                    .iter()
+                    .map(|c| c.spec.colspan * c.spec.duplicate)
+                    .sum()
+            })
+            .unwrap_or(0);
+        let columns = match attrs.get("cols") {
+            Some(cols) => ColumnSpec::parse_cols(cols)?,
+            None => vec![ColumnSpec::default(); first_row_width],
+        };
+        // The first line is an implicit header if it holds a whole row and is followed by an
+        // empty line
+        let implicit_header = groups.first().map(|(n, _)| *n == 0).unwrap_or(false)
+            && lines.get(1).map(|l| l.trim().is_empty()).unwrap_or(false)
+            && first_row_width == columns.len();
+        let has_header = if attrs.has_option("noheader") {
+            false
+        } else {
+            attrs.has_option("header") || implicit_header
+        };
+
+        let cells = groups.into_iter().flat_map(|(_, cells)| cells);
+        let mut rows = Self::rows(cells, columns.len()).into_iter();
+        let header = match has_header {
+            true => rows
+                .next()
+                .map(|row| TableRow::parse(row, &columns, true, origin, state))
+                .transpose()?,
+            false => None,
+        };
+        let mut body = rows
+            .map(|row| TableRow::parse(row, &columns, false, origin, state))
+            .collect::<Result<Vec<_>, _>>()?;
+        let footer = match attrs.has_option("footer") {
+            true => body.pop(),
+            false => None,
+        };
+        Ok(Self {
+            format,
+            separator,
+            columns,
+            header,
+            body,
+            footer,
+        })
+    }
+
+    /// Arrange cells into rows of `width` columns, accounting for spans. An incomplete last
+    /// row is kept.
+    fn rows(cells: impl Iterator<Item = RawCell>, width: usize) -> Vec<Vec<RawCell>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        // Column spans and remaining rows of cells spanning into later rows
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        let mut filled = 0;
+        let cells = cells.flat_map(|cell| {
+            let n = cell.spec.duplicate;
+            std::iter::repeat_n(cell, n)
+        });
+        for cell in cells {
+            filled += cell.spec.colspan;
+            if cell.spec.rowspan > 1 {
+                spans.push((cell.spec.colspan, cell.spec.rowspan - 1));
+            }
+            row.push(cell);
+            if filled >= width.max(1) {
+                rows.push(std::mem::take(&mut row));
+                // The next row starts with the columns spanned from above
+                filled = spans.iter().map(|(colspan, _)| colspan).sum();
+                for (_, remaining) in spans.iter_mut() {
+                    *remaining -= 1;
+                }
+                spans.retain(|(_, remaining)| *remaining > 0);
+            }
+        }
+        if !row.is_empty() {
+            rows.push(row);
+        }
+        rows
+    }
+}
+
+impl TableRow {
+    fn parse(
+        cells: Vec<RawCell>,
+        columns: &[ColumnSpec],
+        header: bool,
+        origin: Position,
+        state: &mut ParserState,
+    ) -> Result<Self, ParserError> {
+        let mut column = 0;
+        let mut row = Vec::new();
+        for cell in cells {
+            let col = columns.get(column).copied().unwrap_or_default();
+            column += cell.spec.colspan;
+            row.push(TableCell::parse(cell, &col, header, origin, state)?);
+        }
+        Ok(Self(row))
+    }
+}
+
+impl TableCell {
+    /// Parse a cell of a table whose content starts at `origin`. AsciiDoc cells are parsed
+    /// as nested documents.
+    fn parse(
+        cell: RawCell,
+        column: &ColumnSpec,
+        header: bool,
+        origin: Position,
+        state: &mut ParserState,
+    ) -> Result<Self, ParserError> {
+        let mut spec = cell.spec;
+        spec.halign = spec.halign.or(column.halign);
+        spec.valign = spec.valign.or(column.valign);
+        // Header cells do not inherit the column style
+        if !header {
+            spec.style = spec.style.or(column.style);
+        }
+        let style = spec.style.unwrap_or(CellStyle::Default);
+        // Literal cells keep their indentation
+        let text = match style {
+            CellStyle::Literal => cell.text.trim_start_matches('\n'),
+            _ => cell.text.trim_start(),
+        };
+        let start = origin
+            .offset(cell.line, cell.column)
+            .advance(&cell.text[..cell.text.len() - text.len()]);
+        let text = text.trim_end();
+        let span = Span {
+            start,
+            end: start.advance(text),
+        };
+        let lines: Vec<String> = text.lines().map(str::to_owned).collect();
+        let blocks = match style {
+            CellStyle::AsciiDoc if !header => {
+                // Attribute entries stay local to the cell, IDs are shared with the parent
+                let mut nested = state.nested();
+                let blocks =
+                    Block::parse_lines(&lines, start, &mut nested, SectionScope::Document)?;
+                state.join(nested);
+                blocks
+            }
+            CellStyle::Literal => {
+                let context = BlockContext::Literal(VerbatimBlock::new(
+                    &lines,
+                    &Substitutions::verbatim(),
+                    state,
+                ));
+                vec![Block::new(context, span)]
+            }
+            CellStyle::Verse => {
+                let content =
+                    BlockContent::Simple(SimpleBlock::new(&lines, &Substitutions::normal(), state));
+                let context = BlockContext::Verse(QuoteBlock::new(content, None));
+                vec![Block::new(context, span)]
+            }
+            _ => Self::paragraphs(&lines, start, state),
+        };
+        Ok(Self {
+            spec,
+            block: Block::new(BlockContext::TableCell(CompoundBlock(blocks)), span),
+        })
+    }
+
+    /// Lines of a cell starting at `start`, split into paragraphs at empty lines
+    fn paragraphs(lines: &[String], start: Position, state: &ParserState) -> Vec<Block> {
+        let mut paragraphs = Vec::new();
+        let mut paragraph: Vec<String> = Vec::new();
+        let mut paragraph_start = start;
+        let empty = String::new();
+        for (n, line) in lines.iter().chain(std::iter::once(&empty)).enumerate() {
+            if line.trim().is_empty() {
+                if !paragraph.is_empty() {
+                    let context = BlockContext::Paragraph(SimpleBlock::new(
+                        &paragraph,
+                        &Substitutions::normal(),
+                        state,
+                    ));
+                    let span = Span {
+                        start: paragraph_start,
+                        end: paragraph_start.advance(&paragraph.join("\n")),
+                    };
+                    paragraphs.push(Block::new(context, span));
+                    paragraph.clear();
+                }
+            } else {
+                if paragraph.is_empty() {
+                    paragraph_start = start.offset(n, 0);
+                }
+                paragraph.push(line.to_owned());
+            }
+        }
+        paragraphs
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{document::Document, inline::Inline};
+
+    fn table(s: &str) -> TableBlock {
+        let doc = Document::parse(s).unwrap();
+        match doc.blocks.into_iter().next().map(|b| b.context) {
+            Some(BlockContext::Table(table)) => table,
+            other => panic!("expected a table, got {:?}", other),
+        }
+    }
+
+    fn text(cell: &TableCell) -> String {
+        match &cell.block.context {
+            BlockContext::TableCell(CompoundBlock(blocks)) => match &blocks[..] {
+                [Block {
+                    context: BlockContext::Paragraph(SimpleBlock(inlines)),
+                    ..
+                }] => match &inlines[..] {
+                    [Inline {
+                        kind: crate::ast::inline::InlineKind::Unquoted(s),
+                        ..
+                    }] => s.clone(),
+                    _ => panic!("unexpected inlines {:?}", inlines),
+                },
+                _ => panic!("unexpected cell content {:?}", blocks),
+            },
+            _ => panic!("not a cell"),
+        }
+    }
+
+    fn texts(row: &TableRow) -> Vec<String> {
+        row.0.iter().map(text).collect()
+    }
+
+    #[test]
+    fn psv_table_with_implicit_header() {
+        let t = table("|===\n|Name |Value\n\n|a |1\n|b |2\n|===");
+        assert_eq!(t.format, TableFormat::Psv);
+        assert_eq!(t.columns.len(), 2);
+        assert_eq!(texts(t.header.as_ref().unwrap()), vec!["Name", "Value"]);
+        assert_eq!(t.body.len(), 2);
+        assert_eq!(texts(&t.body[1]), vec!["b", "2"]);
+    }
+
+    #[test]
+    fn csv_table() {
+        let t = table(
+            "[format=csv, options=header]\n|===\nName,Description\nfoo,\"multi\nline, quoted\"\nbar,\"say \"\"hi\"\"\"\n|===",
+        );
+        assert_eq!(t.format, TableFormat::Csv);
+        assert_eq!(t.separator, ',');
+        assert_eq!(
+            texts(t.header.as_ref().unwrap()),
+            vec!["Name", "Description"]
+        );
+        assert_eq!(texts(&t.body[0]), vec!["foo", "multi\nline, quoted"]);
+        assert_eq!(texts(&t.body[1]), vec!["bar", "say \"hi\""]);
+    }
+
+    #[test]
+    fn csv_and_dsv_shorthand_delimiters() {
+        let t = table(",===\na,b\nc,d\n,===");
+        assert_eq!(t.format, TableFormat::Csv);
+        assert!(t.header.is_none());
+        assert_eq!(t.body.len(), 2);
+
+        let t = table(":===\na:b\\:c\n\nd:e\n:===");
+        assert_eq!(t.format, TableFormat::Dsv);
+        assert_eq!(texts(t.header.as_ref().unwrap()), vec!["a", "b:c"]);
+        assert_eq!(texts(&t.body[0]), vec!["d", "e"]);
+    }
+
+    #[test]
+    fn custom_separator() {
+        let t = table("[format=csv, separator=;]\n|===\na;b\n|===");
+        assert_eq!(t.separator, ';');
+        assert_eq!(texts(&t.body[0]), vec!["a", "b"]);
+
+        let t = table("[separator=¦]\n|===\n¦a ¦b|c\n|===");
+        assert_eq!(texts(&t.body[0]), vec!["a", "b|c"]);
+    }
+
+    #[test]
+    fn cols_and_spans() {
+        let t = table("[cols=\"1,1,1\"]\n|===\n2+|wide |x\n.2+|tall |y |z\n|w |v\n|===");
+        assert_eq!(t.body.len(), 3);
+        assert_eq!(t.body[0].0.len(), 2);
+        assert_eq!(t.body[0].0[0].spec.colspan, 2);
+        assert_eq!(t.body[1].0.len(), 3);
+        assert_eq!(texts(&t.body[2]), vec!["w", "v"]);
+
+        let t = table("[cols=2]\n|===\n3*|x |y\n|===");
+        assert_eq!(t.body.len(), 2);
+        assert_eq!(texts(&t.body[1]), vec!["x", "y"]);
+    }
+
+    #[test]
+    fn nested_table_in_asciidoc_cell() {
+        let t = table("[cols=\"1,2a\"]\n|===\n|outer\n|\n[format=csv]\n!===\nx,y\n!===\n\n!===\n!a !b\n!===\n|===");
+        let cell = &t.body[0].0[1];
+        assert_eq!(cell.spec.style, Some(CellStyle::AsciiDoc));
+        let blocks = match &cell.block.context {
+            BlockContext::TableCell(CompoundBlock(blocks)) => blocks,
+            _ => panic!("not a cell"),
+        };
+        assert_eq!(blocks.len(), 2);
+        for (block, format) in blocks.iter().zip([TableFormat::Csv, TableFormat::Psv]) {
+            match &block.context {
+                BlockContext::Table(nested) => {
+                    assert_eq!(nested.format, format);
+                    assert_eq!(nested.body[0].0.len(), 2);
+                }
+                other => panic!("expected a nested table, got {:?}", other),
+            }
+        }
+        match &blocks[1].context {
+            BlockContext::Table(nested) => {
+                assert_eq!(nested.separator, '!');
+                assert_eq!(texts(&nested.body[0]), vec!["a", "b"]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn cell_blocks(cell: &TableCell) -> &[Block] {
+        match &cell.block.context {
+            BlockContext::TableCell(CompoundBlock(blocks)) => blocks,
+            _ => panic!("not a cell"),
+        }
+    }
+
+    #[test]
+    fn asciidoc_cell_is_nested_document() {
+        let doc = Document::parse(
+            ":idprefix: id_\n\n== Intro\n\n|===\na|\n:local: yes\n\n== Intro\n\n* one\n* two\n\n----\ncode\n----\n|===\n\n== After",
+        )
+        .unwrap();
+        assert!(!doc.attrs.is_set("local"));
+        let intro = match &doc.blocks[0].context {
+            BlockContext::Section(section) => section,
+            other => panic!("expected a section, got {:?}", other),
+        };
+        let t = match &intro.blocks[0].context {
+            BlockContext::Table(t) => t,
+            other => panic!("expected a table, got {:?}", other),
+        };
+        let blocks = cell_blocks(&t.body[0].0[0]);
+        // The section in the cell does not close the enclosing section, and uses the parent
+        // attributes and IDs
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].anchor.as_deref(), Some("id_intro_2"));
+        match &blocks[0].context {
+            BlockContext::Section(section) => {
+                assert!(matches!(
+                    section.blocks[0].context,
+                    BlockContext::UnorderedList(_)
+                ));
+                assert!(matches!(
+                    section.blocks[1].context,
+                    BlockContext::Listing(_)
+                ));
+            }
+            other => panic!("expected a section, got {:?}", other),
+        }
+        assert_eq!(doc.blocks.len(), 2);
+        assert_eq!(doc.blocks[1].anchor.as_deref(), Some("id_after"));
+    }
+
+    #[test]
+    fn cell_positions() {
+        let t = table("[cols=\"1,1a\"]\n|===\n|first |\n----\ncode\n----\n|===");
+        let cell = &t.body[0].0[0];
+        assert_eq!(cell.block.span.start, Position::new(3, 2));
+        assert_eq!(cell.block.span.end, Position::new(3, 7));
+        let blocks = cell_blocks(&t.body[0].0[1]);
+        assert_eq!(blocks[0].span.start, Position::new(4, 1));
+        assert_eq!(blocks[0].span.end, Position::new(6, 5));
+
+        let t = table(",===\na,  b\n,===");
+        assert_eq!(t.body[0].0[1].block.span.start, Position::new(2, 5));
+    }
+}
@@ -0,0 +1,162 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::{
+    inline::{Inline, InlineKind},
+    macros,
+    subs::{replace_all, Node},
+};
+
+/// A user interface macro. These are experimental, and only parsed when the `experimental`
+/// attribute is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UiMacro {
+    /// `kbd:[Ctrl+Shift+T]` or `kbd:[Ctrl,T]`, a key combination
+    Kbd(Vec<String>),
+    /// `btn:[Save]`
+    Button(String),
+    /// `menu:File[Save > As]`, a menu and the path of items in it
+    Menu { menu: String, items: Vec<String> },
+}
+
+/// Substitute UI macros in text nodes. Keys, buttons and menu items are plain text.
+pub fn substitute_ui_macros(mut nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_KBD: Regex = Regex::new(r"(\\)?kbd:\[((?:\\\]|[^\]])+?)\]").unwrap();
+        static ref RE_BTN: Regex = Regex::new(r"(\\)?btn:\[((?:\\\]|[^\]])+?)\]").unwrap();
+        static ref RE_MENU: Regex =
+            Regex::new(r"(\\)?menu:(\w[^\n\[]*?)\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_KBD, |caps| {
+            ui_macro(caps, || {
+                UiMacro::Kbd(keys(&macros::unescape(&s.text(&caps[2]))))
+            })
+        })
+    });
+    nodes = Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_BTN, |caps| {
+            ui_macro(caps, || {
+                UiMacro::Button(macros::unescape(&s.text(&caps[2])))
+            })
+        })
+    });
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MENU, |caps| {
+            ui_macro(caps, || {
+                let path = macros::unescape(&s.text(&caps[3]));
+                let items = path
+                    .split('>')
+                    .map(str::trim)
+                    .filter(|i| !i.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                UiMacro::Menu {
+                    menu: s.text(caps[2].trim()),
+                    items,
+                }
+            })
+        })
+    })
+}
+
+fn ui_macro<F: Fn() -> UiMacro>(caps: &regex::Captures, f: F) -> Option<Vec<Node>> {
+    let node = match caps.get(1) {
+        Some(_) => Node::Literal(caps[0][1..].to_owned()),
+        None => {
+            let inline = Inline::new(InlineKind::Ui(f()));
+            Node::Inline(inline, Vec::new())
+        }
+    };
+    Some(vec![node])
+}
+
+/// Keys of a combination, separated by `,` if there is one, or else by `+`. A trailing
+/// separator is a key itself, as in `Ctrl++`.
+fn keys(s: &str) -> Vec<String> {
+    let s = s.trim();
+    if s == "+" || s == "," {
+        return vec![s.to_owned()];
+    }
+    let separator = match s.contains(',') {
+        true => ',',
+        false => '+',
+    };
+    let (s, last) = match s.strip_suffix(separator) {
+        Some(rest) => (rest, Some(separator.to_string())),
+        None => (s, None),
+    };
+    s.split(separator)
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_owned)
+        .chain(last)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{document::Document, subs::apply_with};
+
+    fn ui(s: &str) -> Vec<UiMacro> {
+        apply_with(s, substitute_ui_macros)
+            .into_iter()
+            .filter_map(|i| match i.kind {
+                InlineKind::Ui(m) => Some(m),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn key_combinations() {
+        assert_eq!(keys("Ctrl+Shift+T"), vec!["Ctrl", "Shift", "T"]);
+        assert_eq!(keys("Ctrl, +"), vec!["Ctrl", "+"]);
+        assert_eq!(keys("Ctrl++"), vec!["Ctrl", "+"]);
+        assert_eq!(keys("F11"), vec!["F11"]);
+    }
+
+    #[test]
+    fn ui_macros() {
+        assert_eq!(
+            ui("Press kbd:[Ctrl+S] or btn:[Save], then menu:File[Export > As PDF]."),
+            vec![
+                UiMacro::Kbd(vec!["Ctrl".to_owned(), "S".to_owned()]),
+                UiMacro::Button("Save".to_owned()),
+                UiMacro::Menu {
+                    menu: "File".to_owned(),
+                    items: vec!["Export".to_owned(), "As PDF".to_owned()]
+                },
+            ]
+        );
+        assert_eq!(ui("\\kbd:[F1] menu:Help[]").len(), 1);
+    }
+
+    #[test]
+    fn plain_text_labels() {
+        let doc = Document::parse(":experimental:\n\nbtn:[Don't] menu:File[Save...]").unwrap();
+        let ui: Vec<_> = doc.find_inlines(|i| match &i.kind {
+            InlineKind::Ui(m) => Some(m.clone()),
+            _ => None,
+        });
+        assert_eq!(
+            ui,
+            vec![
+                UiMacro::Button("Don\u{2019}t".to_owned()),
+                UiMacro::Menu {
+                    menu: "File".to_owned(),
+                    items: vec!["Save\u{2026}".to_owned()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn experimental_ui_macros() {
+        let doc = Document::parse("Press kbd:[F1].").unwrap();
+        assert_eq!(doc.inline().unwrap().len(), 1);
+        let doc = Document::parse(":experimental:\n\nPress kbd:[F1].").unwrap();
+        assert!(matches!(doc.inline().unwrap()[1].kind, InlineKind::Ui(_)));
+    }
+}
@@ -0,0 +1,212 @@
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+use super::{
+    block::Block,
+    catalog::Catalog,
+    diagnostic::Diagnostic,
+    document::DocumentAttrs,
+    inline::{Inline, InlineKind},
+    macros,
+    subs::{replace_all, Node, Stashed},
+};
+
+/// A cross-reference, `<<id>>`, `<<id,text>>` or `xref:target[text]`. The text is the
+/// content of the inline; when empty, the reference text of the target is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xref {
+    /// Path of another document, such as `other.adoc`
+    pub path: Option<String>,
+    /// ID of the target in the document
+    pub id: Option<String>,
+    pub content: Vec<Inline>,
+    /// Text of the target, set when the reference is resolved
+    pub reftext: Option<String>,
+}
+
+impl Xref {
+    /// A reference to `target`, `id`, `path#id`, `#id` or `path.adoc`. Paths without an
+    /// extension are AsciiDoc documents.
+    fn new(target: &str, content: Vec<Inline>) -> Self {
+        let (path, id) = match target.split_once('#') {
+            Some(("", id)) => (None, Some(id)),
+            Some((path, id)) => (Some(path), Some(id).filter(|id| !id.is_empty())),
+            None if target.ends_with(".adoc") => (Some(target), None),
+            None => (None, Some(target)),
+        };
+        let path = path.map(|p| match p.rsplit('/').next().unwrap_or(p).contains('.') {
+            true => p.to_owned(),
+            false => format!("{}.adoc", p),
+        });
+        Self {
+            path,
+            id: id.map(str::to_owned),
+            content,
+            reftext: None,
+        }
+    }
+}
+
+/// Substitute cross-references in text nodes. Targets are taken as they are written.
+pub fn substitute_xrefs(mut nodes: Vec<Node>) -> Vec<Node> {
+    lazy_static! {
+        static ref RE_ANGLE: Regex =
+            Regex::new(r"(\\)?<<([\w#/.:\-][^,>\n]*?)(?:, *((?s).+?))?>>").unwrap();
+        static ref RE_MACRO: Regex =
+            Regex::new(r"(\\)?xref:([^\s\[]+)\[((?:\\\]|[^\]])*)\]").unwrap();
+    }
+    nodes = Node::substitute(nodes, &|s| replace_all(s, &RE_ANGLE, |caps| xref(s, caps)));
+    Node::substitute(nodes, &|s| {
+        replace_all(s, &RE_MACRO, |caps| {
+            let text = macros::unescape(&caps[3]);
+            xref_with(caps, &s.source(&caps[2]), Some(text.as_str()))
+        })
+    })
+}
+
+fn xref(s: &Stashed, caps: &Captures) -> Option<Vec<Node>> {
+    let target = s.source(caps[2].trim());
+    xref_with(caps, &target, caps.get(3).map(|t| t.as_str().trim()))
+}
+
+fn xref_with(caps: &Captures, target: &str, text: Option<&str>) -> Option<Vec<Node>> {
+    if caps.get(1).is_some() {
+        return Some(vec![Node::Literal(caps[0][1..].to_owned())]);
+    }
+    let inline = Inline::new(InlineKind::Xref(Xref::new(target, Vec::new())));
+    let content = text
+        .filter(|t| !t.is_empty())
+        .map(|t| vec![Node::Text(t.to_owned())])
+        .unwrap_or_default();
+    Some(vec![Node::Inline(inline, content)])
+}
+
+/// Resolve the references in `blocks` to targets in `catalog`. References to other
+/// documents are not checked. A reference to a missing ID may be a reference by title,
+/// like `<<Section Title>>`; other references to missing IDs are reported.
+pub fn resolve(blocks: &mut [Block], catalog: &Catalog, attrs: &DocumentAttrs) -> Vec<Diagnostic> {
+    let xrefstyle = attrs.get("xrefstyle");
+    let mut diagnostics = Vec::new();
+    Block::walk_inlines_mut(blocks, &mut |inline, span| {
+        let xref = match &mut inline.kind {
+            InlineKind::Xref(xref @ Xref { path: None, .. }) => xref,
+            _ => return,
+        };
+        let id = xref.id.clone().unwrap_or_default();
+        let id = match catalog.refs.contains_key(&id) {
+            true => id,
+            false => match catalog.find_by_title(&id) {
+                Some(found) => found.to_owned(),
+                None => {
+                    diagnostics.push(Diagnostic {
+                        span,
+                        message: format!("possible invalid reference: {}", id),
+                    });
+                    return;
+                }
+            },
+        };
+        xref.reftext = catalog.refs[&id].xref_text(xrefstyle);
+        xref.id = Some(id);
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{document::Document, subs::apply_with};
+
+    fn xref(inline: &Inline) -> &Xref {
+        match &inline.kind {
+            InlineKind::Xref(xref) => xref,
+            other => panic!("expected a cross-reference, got {:?}", other),
+        }
+    }
+
+    /// All cross-references in a document
+    fn resolved(doc: &Document) -> Vec<Xref> {
+        doc.find_inlines(|i| match &i.kind {
+            InlineKind::Xref(x) => Some(x.clone()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn xref_forms() {
+        let x = apply_with(
+            "<<intro>>, <<intro, the intro>>, xref:other.adoc#sec[] xref:guide#[Guide]",
+            substitute_xrefs,
+        );
+        assert_eq!(xref(&x[0]).id.as_deref(), Some("intro"));
+        assert!(xref(&x[0]).content.is_empty());
+        assert_eq!(xref(&x[2]).content, vec![Inline::unquoted("the intro")]);
+        assert_eq!(xref(&x[4]).path.as_deref(), Some("other.adoc"));
+        assert_eq!(xref(&x[4]).id.as_deref(), Some("sec"));
+        assert_eq!(xref(&x[6]).path.as_deref(), Some("guide.adoc"));
+        assert_eq!(xref(&x[6]).id, None);
+        assert_eq!(
+            apply_with("\\<<intro>>", substitute_xrefs),
+            vec![Inline::unquoted("<<intro>>")]
+        );
+    }
+
+    #[test]
+    fn xref_styles() {
+        let text = |style: &str| {
+            let doc = Document::parse(&format!(
+                ":sectnums:\n:xrefstyle: {}\n\n== Install\n\n[appendix]\n== Extra\n\n\
+                 [#t,reftext=The Table]\n|===\n|a\n|===\n\n<<_install>> <<_extra>> <<t>>",
+                style
+            ))
+            .unwrap();
+            resolved(&doc)
+                .into_iter()
+                .map(|x| x.reftext.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            text("full"),
+            vec![
+                "Section 1, \u{201c}Install\u{201d}",
+                "Appendix A, \u{201c}Extra\u{201d}",
+                "The Table"
+            ]
+        );
+        assert_eq!(text("short"), vec!["Section 1", "Appendix A", "The Table"]);
+        assert_eq!(text("basic"), vec!["Install", "Extra", "The Table"]);
+    }
+
+    #[test]
+    fn unresolved_references() {
+        let doc =
+            Document::parse("== Install\n\nSee <<missing>>, <<Install>> and xref:other.adoc#x[].")
+                .unwrap();
+        let x = resolved(&doc);
+        assert_eq!(x[1].id.as_deref(), Some("_install"));
+        assert_eq!(x[1].reftext.as_deref(), Some("Install"));
+        assert_eq!(doc.diagnostics.len(), 1);
+        assert_eq!(
+            doc.diagnostics[0].message,
+            "possible invalid reference: missing"
+        );
+        assert_eq!(doc.diagnostics[0].span.start.line, 3);
+    }
+
+    #[test]
+    fn replacements_in_ids() {
+        let doc = Document::parse("[[a--b]]\n== It's\n\nSee <<a--b>> and xref:a--b[].").unwrap();
+        assert!(doc.diagnostics.is_empty());
+        let x = resolved(&doc);
+        assert_eq!(x[0].id.as_deref(), Some("a--b"));
+        assert_eq!(x[0].reftext.as_deref(), Some("It\u{2019}s"));
+        assert_eq!(x[1].id.as_deref(), Some("a--b"));
+    }
+
+    #[test]
+    fn duplicate_titles() {
+        let doc = Document::parse(&format!("{}<<Setup>>", "== Setup\n\n".repeat(10))).unwrap();
+        assert_eq!(doc.catalog.find_by_title("Setup"), Some("_setup"));
+        assert_eq!(resolved(&doc)[0].id.as_deref(), Some("_setup"));
+    }
+}
@@ -1 +1 @@
-mod ast;
+pub mod ast;